@@ -6,14 +6,30 @@
 }:
 let
   cfg = config.services.sysnotifier;
+  tomlFormat = pkgs.formats.toml { };
 in
 {
   options.services.sysnotifier = {
     enable = lib.mkEnableOption "sysnotifier";
     package = lib.mkPackageOption pkgs "sysnotifier" { };
+
+    settings = lib.mkOption {
+      type = tomlFormat.type;
+      default = { };
+      example = lib.literalExpression ''
+        {
+          battery.charging_progress = true;
+        }
+      '';
+      description = "Configuration written to {file}`$XDG_CONFIG_HOME/sysnotifier/config.toml`.";
+    };
   };
 
   config = lib.mkIf cfg.enable {
+    xdg.configFile."sysnotifier/config.toml" = lib.mkIf (cfg.settings != { }) {
+      source = tomlFormat.generate "sysnotifier-config" cfg.settings;
+    };
+
     systemd.user.services.sysnotifier = {
       Install = {
         WantedBy = [ "graphical-session.target" ];
@@ -23,6 +39,9 @@ in
         Description = "Pulse Audio and Notification bridge";
         PartOf = [ "graphical-session.target" ];
         After = [ "graphical-session.target" ];
+        X-Restart-Triggers = lib.mkIf (cfg.settings != { }) [
+          "${config.xdg.configFile."sysnotifier/config.toml".source}"
+        ];
       };
 
       Service = {
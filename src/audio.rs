@@ -0,0 +1,237 @@
+use crate::Event;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::mpsc};
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Volume {
+    pub value: u32,
+    pub muted: bool,
+}
+
+/// The active profile of a card, e.g. A2DP or HFP for Bluetooth headsets.
+#[derive(PartialEq, Clone, Debug)]
+pub struct CardProfile {
+    pub card: String,
+    pub name: String,
+    pub description: String,
+}
+
+impl CardProfile {
+    /// Headset profiles trade audio quality for a microphone.
+    pub fn is_headset(&self) -> bool {
+        ["headset", "handsfree", "hsp", "hfp"]
+            .iter()
+            .any(|kind| self.name.contains(kind))
+    }
+}
+
+/// The active port of a sink, e.g. speakers or headphones.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SinkPort {
+    pub sink: String,
+    pub name: String,
+    pub description: String,
+}
+
+impl SinkPort {
+    pub fn is_headphones(&self) -> bool {
+        self.name.contains("headphones")
+    }
+}
+
+/// Active profiles and ports by card and sink index.
+#[derive(Default, Clone)]
+pub struct AudioRoutes {
+    pub profiles: HashMap<u32, CardProfile>,
+    pub ports: HashMap<u32, SinkPort>,
+}
+
+/// Changes carry the index of their card or sink.
+pub enum AudioRouteChange {
+    Profile(u32, CardProfile),
+    Port {
+        sink: u32,
+        previous: SinkPort,
+        current: SinkPort,
+    },
+}
+
+impl AudioRoutes {
+    /// What changed for cards and sinks present in both, new and removed
+    /// devices are left to other notifications.
+    pub fn changes(&self, current: &AudioRoutes) -> Vec<AudioRouteChange> {
+        let mut changes = Vec::new();
+        for (index, profile) in &current.profiles {
+            if self
+                .profiles
+                .get(index)
+                .is_some_and(|previous| previous.name != profile.name)
+            {
+                changes.push(AudioRouteChange::Profile(*index, profile.clone()));
+            }
+        }
+        for (index, port) in &current.ports {
+            if let Some(previous) = self.ports.get(index)
+                && previous.name != port.name
+            {
+                changes.push(AudioRouteChange::Port {
+                    sink: *index,
+                    previous: previous.clone(),
+                    current: port.clone(),
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// What `SysNotifier` needs from the sound server, implemented on top of
+/// libpulse and, with the `pipewire` feature, the native PipeWire API.
+pub trait AudioBackend {
+    fn default_sink_volume(&mut self) -> anyhow::Result<Volume>;
+
+    fn default_source_volume(&mut self) -> anyhow::Result<Volume>;
+
+    /// Human readable name of the default sink, e.g. "Built-in Audio Analog Stereo".
+    fn default_sink_description(&mut self) -> anyhow::Result<String>;
+
+    /// Names of the applications recording from a microphone.
+    fn recording_applications(&mut self) -> anyhow::Result<Vec<String>>;
+
+    fn audio_routes(&mut self) -> anyhow::Result<AudioRoutes>;
+
+    /// Sends [`Event::VolumeChanged`], [`Event::SourceVolumeChanged`],
+    /// [`Event::AudioRoutesChanged`] and, if `recordings` is set,
+    /// [`Event::SourceOutputsChanged`] as things change.
+    fn subscribe(&mut self, event_sender: mpsc::Sender<Event>, recordings: bool);
+}
+
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    /// PipeWire when built with it and running, PulseAudio otherwise.
+    #[default]
+    Auto,
+    Pulse,
+    PipeWire,
+}
+
+/// Connects to the configured sound server.
+pub fn connect(kind: BackendKind) -> anyhow::Result<Box<dyn AudioBackend>> {
+    match kind {
+        #[cfg(feature = "pipewire")]
+        BackendKind::Auto => match crate::pipewire::PipeWireManager::new() {
+            Ok(pipewire) => Ok(Box::new(pipewire)),
+            Err(e) => {
+                eprintln!("PipeWire unavailable, falling back to PulseAudio: {e}");
+                Ok(Box::new(crate::pulse::PulseManager::new()?))
+            }
+        },
+        #[cfg(not(feature = "pipewire"))]
+        BackendKind::Auto => Ok(Box::new(crate::pulse::PulseManager::new()?)),
+        BackendKind::Pulse => Ok(Box::new(crate::pulse::PulseManager::new()?)),
+        #[cfg(feature = "pipewire")]
+        BackendKind::PipeWire => Ok(Box::new(crate::pipewire::PipeWireManager::new()?)),
+        #[cfg(not(feature = "pipewire"))]
+        BackendKind::PipeWire => anyhow::bail!("Built without the pipewire feature"),
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[derive(Default)]
+    struct State {
+        sink: Option<Volume>,
+        sink_description: String,
+        source: Option<Volume>,
+        routes: AudioRoutes,
+        recordings: Vec<String>,
+        event_sender: Option<mpsc::Sender<Event>>,
+    }
+
+    impl State {
+        fn send(&self, event: Event) {
+            if let Some(event_sender) = &self.event_sender {
+                _ = event_sender.send(event);
+            }
+        }
+    }
+
+    /// An in-memory sound server, clones share the same state so tests can
+    /// keep a handle after boxing one as the backend.
+    #[derive(Clone, Default)]
+    pub struct MockBackend(Rc<RefCell<State>>);
+
+    impl MockBackend {
+        pub fn set_sink_volume(&self, value: u32, muted: bool) {
+            let mut state = self.0.borrow_mut();
+            state.sink = Some(Volume { value, muted });
+            state.send(Event::VolumeChanged);
+        }
+
+        pub fn set_sink_description(&self, description: &str) {
+            self.0.borrow_mut().sink_description = description.into();
+        }
+
+        pub fn set_source_volume(&self, value: u32, muted: bool) {
+            let mut state = self.0.borrow_mut();
+            state.source = Some(Volume { value, muted });
+            state.send(Event::SourceVolumeChanged);
+        }
+
+        pub fn set_port(&self, sink: u32, port: SinkPort) {
+            let mut state = self.0.borrow_mut();
+            state.routes.ports.insert(sink, port);
+            state.send(Event::AudioRoutesChanged);
+        }
+
+        pub fn set_profile(&self, card: u32, profile: CardProfile) {
+            let mut state = self.0.borrow_mut();
+            state.routes.profiles.insert(card, profile);
+            state.send(Event::AudioRoutesChanged);
+        }
+
+        pub fn set_recordings(&self, applications: &[&str]) {
+            let mut state = self.0.borrow_mut();
+            state.recordings = applications.iter().map(|name| name.to_string()).collect();
+            state.send(Event::SourceOutputsChanged);
+        }
+    }
+
+    impl AudioBackend for MockBackend {
+        fn default_sink_volume(&mut self) -> anyhow::Result<Volume> {
+            let state = self.0.borrow();
+            state
+                .sink
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("No default sink"))
+        }
+
+        fn default_source_volume(&mut self) -> anyhow::Result<Volume> {
+            let state = self.0.borrow();
+            state
+                .source
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("No default source"))
+        }
+
+        fn default_sink_description(&mut self) -> anyhow::Result<String> {
+            Ok(self.0.borrow().sink_description.clone())
+        }
+
+        fn recording_applications(&mut self) -> anyhow::Result<Vec<String>> {
+            Ok(self.0.borrow().recordings.clone())
+        }
+
+        fn audio_routes(&mut self) -> anyhow::Result<AudioRoutes> {
+            Ok(self.0.borrow().routes.clone())
+        }
+
+        fn subscribe(&mut self, event_sender: mpsc::Sender<Event>, _recordings: bool) {
+            self.0.borrow_mut().event_sender = Some(event_sender);
+        }
+    }
+}
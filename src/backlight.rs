@@ -0,0 +1,96 @@
+use crate::Event;
+use crate::config::BacklightConfig;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::Duration,
+};
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// A backlight class device, e.g. `intel_backlight`.
+struct Backlight {
+    path: PathBuf,
+}
+
+impl Backlight {
+    /// The configured device, or the one most likely to drive the panel
+    /// directly, preferring firmware over platform over raw interfaces like
+    /// logind does.
+    fn find(device: Option<&str>) -> Option<Self> {
+        let path = match device {
+            Some(device) => Path::new(BACKLIGHT_DIR).join(device),
+            None => fs::read_dir(BACKLIGHT_DIR)
+                .ok()?
+                .flatten()
+                .map(|entry| entry.path())
+                .min_by_key(|path| {
+                    let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+                    let rank = ["firmware", "platform", "raw"]
+                        .iter()
+                        .position(|preferred| kind.trim() == *preferred);
+                    (rank.unwrap_or(usize::MAX), path.clone())
+                })?,
+        };
+
+        path.join("brightness").exists().then_some(Self { path })
+    }
+
+    fn read(&self, attribute: &str) -> Option<u64> {
+        fs::read_to_string(self.path.join(attribute))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Brightness in percent of `max_brightness`, rounded.
+    fn percentage(&self) -> Option<u32> {
+        let max = self.read("max_brightness").filter(|max| *max > 0)?;
+        let brightness = self.read("brightness")?.min(max);
+        Some(((brightness * 100 + max / 2) / max) as u32)
+    }
+}
+
+pub struct BacklightMonitor {
+    config: BacklightConfig,
+}
+
+impl BacklightMonitor {
+    pub fn new(config: BacklightConfig) -> Self {
+        Self { config }
+    }
+
+    /// Sysfs attributes can't be waited on, so the brightness is polled like
+    /// lock key LEDs. The brightness at startup isn't announced.
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) {
+        if !self.config.enabled {
+            return;
+        }
+        let Some(backlight) = Backlight::find(self.config.device.as_deref()) else {
+            return;
+        };
+        let Some(mut last) = backlight.percentage() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(self.config.interval_ms.max(50)));
+
+            loop {
+                interval.tick().await;
+
+                if let Some(percentage) = backlight.percentage()
+                    && percentage != last
+                {
+                    last = percentage;
+                    if event_sender.send(Event::Brightness(percentage)).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
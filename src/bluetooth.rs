@@ -0,0 +1,291 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use std::{collections::HashMap, sync::mpsc};
+use zbus::{fdo::ObjectManagerProxy, proxy, zvariant::OwnedObjectPath};
+
+const DEVICE_INTERFACE: &str = "org.bluez.Device1";
+const BATTERY_INTERFACE: &str = "org.bluez.Battery1";
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct BluetoothDevice {
+    pub alias: String,
+    /// Freedesktop icon name reported by BlueZ, e.g. `audio-headset`.
+    pub icon: String,
+    pub connected: bool,
+    pub battery: Option<u8>,
+}
+
+#[proxy(interface = "org.bluez.Device1", default_service = "org.bluez")]
+trait Device {
+    #[zbus(property)]
+    fn connected(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn alias(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn icon(&self) -> zbus::Result<String>;
+}
+
+#[proxy(interface = "org.bluez.Battery1", default_service = "org.bluez")]
+trait Battery {
+    #[zbus(property)]
+    fn percentage(&self) -> zbus::Result<u8>;
+}
+
+async fn device_proxy(
+    connection: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<DeviceProxy<'static>> {
+    DeviceProxy::builder(connection).path(path)?.build().await
+}
+
+async fn battery_proxy(
+    connection: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<BatteryProxy<'static>> {
+    BatteryProxy::builder(connection).path(path)?.build().await
+}
+
+async fn describe(connection: &zbus::Connection, path: OwnedObjectPath) -> Option<BluetoothDevice> {
+    let device = device_proxy(connection, path.clone()).await.ok()?;
+    let battery = match battery_proxy(connection, path).await {
+        Ok(battery) => battery.percentage().await.ok(),
+        Err(_) => None,
+    };
+
+    Some(BluetoothDevice {
+        alias: device.alias().await.ok()?,
+        icon: device.icon().await.unwrap_or_default(),
+        connected: device.connected().await.ok()?,
+        battery,
+    })
+}
+
+fn handle_connected(event_sender: &mpsc::Sender<Event>, device: BluetoothDevice) {
+    _ = event_sender.send(Event::BluetoothConnected(device));
+}
+
+fn handle_battery(event_sender: &mpsc::Sender<Event>, device: BluetoothDevice) {
+    _ = event_sender.send(Event::BluetoothBattery(device));
+}
+
+fn watch(
+    connection: &zbus::Connection,
+    path: OwnedObjectPath,
+    interface: &str,
+    event_sender: &mpsc::Sender<Event>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let connection = connection.clone();
+    let event_sender = event_sender.clone();
+
+    match interface {
+        DEVICE_INTERFACE => Some(tokio::spawn(async move {
+            let Ok(device) = device_proxy(&connection, path.clone()).await else {
+                return;
+            };
+            // The stream starts with the current value, which isn't a change.
+            let mut connected = device.connected().await.ok();
+            let mut connected_stream = device.receive_connected_changed().await;
+
+            while let Some(event) = connected_stream.next().await {
+                let Ok(current) = event.get().await else {
+                    continue;
+                };
+                if connected == Some(current) {
+                    continue;
+                }
+                connected = Some(current);
+
+                if let Some(device) = describe(&connection, path.clone()).await {
+                    handle_connected(&event_sender, device);
+                }
+            }
+        })),
+        BATTERY_INTERFACE => Some(tokio::spawn(async move {
+            let Ok(battery) = battery_proxy(&connection, path.clone()).await else {
+                return;
+            };
+            let mut percentage_stream = battery.receive_percentage_changed().await;
+
+            while let Some(event) = percentage_stream.next().await {
+                if event.get().await.is_err() {
+                    continue;
+                }
+                match describe(&connection, path.clone()).await {
+                    Some(device) if device.connected => handle_battery(&event_sender, device),
+                    _ => {}
+                }
+            }
+        })),
+        _ => None,
+    }
+}
+
+pub struct BluetoothManager {
+    connection: zbus::Connection,
+}
+
+impl BluetoothManager {
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+        let manager = Self { connection };
+
+        // Fail early when BlueZ isn't running.
+        manager
+            .object_manager()
+            .await?
+            .get_managed_objects()
+            .await?;
+
+        Ok(manager)
+    }
+
+    async fn object_manager(&self) -> zbus::Result<ObjectManagerProxy<'static>> {
+        ObjectManagerProxy::builder(&self.connection)
+            .destination("org.bluez")?
+            .path("/")?
+            .build()
+            .await
+    }
+
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let object_manager = self.object_manager().await?;
+        let mut added_stream = object_manager.receive_interfaces_added().await?;
+        let mut removed_stream = object_manager.receive_interfaces_removed().await?;
+        let objects = object_manager.get_managed_objects().await?;
+
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            let mut watchers = HashMap::new();
+            for (path, interfaces) in objects {
+                for interface in interfaces.keys() {
+                    if let Some(watcher) =
+                        watch(&connection, path.clone(), interface, &event_sender)
+                    {
+                        watchers.insert((path.clone(), interface.to_string()), watcher);
+                    }
+                }
+            }
+
+            loop {
+                tokio::select! {
+                    Some(signal) = added_stream.next() => {
+                        let Ok(args) = signal.args() else {
+                            continue;
+                        };
+                        let path = OwnedObjectPath::from(args.object_path.clone());
+                        for interface in args.interfaces_and_properties.keys() {
+                            if let Some(watcher) =
+                                watch(&connection, path.clone(), interface, &event_sender)
+                            {
+                                watchers.insert((path.clone(), interface.to_string()), watcher);
+                            }
+                        }
+                    }
+                    Some(signal) = removed_stream.next() => {
+                        let Ok(args) = signal.args() else {
+                            continue;
+                        };
+                        let path = OwnedObjectPath::from(args.object_path.clone());
+                        for interface in args.interfaces.iter() {
+                            if let Some(watcher) =
+                                watchers.remove(&(path.clone(), interface.to_string()))
+                            {
+                                watcher.abort();
+                            }
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::UnixStream;
+    use zbus::interface;
+
+    const PATH: &str = "/org/bluez/hci0/dev_14_3F_A6_28_DC_51";
+
+    struct FakeDevice {
+        connected: bool,
+    }
+
+    #[interface(name = "org.bluez.Device1")]
+    impl FakeDevice {
+        #[zbus(property)]
+        fn connected(&self) -> bool {
+            self.connected
+        }
+
+        #[zbus(property)]
+        fn alias(&self) -> String {
+            "WH-1000XM4".into()
+        }
+
+        #[zbus(property)]
+        fn icon(&self) -> String {
+            "audio-headset".into()
+        }
+    }
+
+    /// A private connection with a paired, connected device on the other end.
+    async fn bluez() -> anyhow::Result<(zbus::Connection, zbus::Connection)> {
+        let (server, client) = UnixStream::pair()?;
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())?
+            .p2p()
+            .serve_at(PATH, FakeDevice { connected: true })?
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        Ok(futures_lite::future::try_zip(server, client).await?)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_connection_changes_are_announced() -> anyhow::Result<()> {
+        let (server, client) = bluez().await?;
+        let (tx, rx) = mpsc::channel();
+
+        let path = OwnedObjectPath::try_from(PATH)?;
+        let watcher = watch(&client, path, DEVICE_INTERFACE, &tx).expect("devices are watched");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        // Like the values read when the property cache fills in.
+        let fake = server
+            .object_server()
+            .interface::<_, FakeDevice>(PATH)
+            .await?;
+        fake.get()
+            .await
+            .connected_changed(fake.signal_emitter())
+            .await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(rx.try_recv().is_err());
+
+        fake.get_mut().await.connected = false;
+        fake.get()
+            .await
+            .connected_changed(fake.signal_emitter())
+            .await?;
+
+        let event = rx.recv_timeout(Duration::from_secs(5))?;
+        assert!(
+            event
+                == Event::BluetoothConnected(BluetoothDevice {
+                    alias: "WH-1000XM4".into(),
+                    icon: "audio-headset".into(),
+                    connected: false,
+                    battery: None,
+                })
+        );
+        watcher.abort();
+        Ok(())
+    }
+}
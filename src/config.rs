@@ -0,0 +1,359 @@
+use crate::audio::BackendKind;
+use crate::leds::LockKey;
+use crate::logind::CriticalAction;
+use crate::osd::OsdSource;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub battery: BatteryConfig,
+    pub health: HealthConfig,
+    pub power_profiles: PowerProfilesConfig,
+    pub critical: CriticalConfig,
+    pub bluetooth: BluetoothConfig,
+    pub disk: DiskConfig,
+    pub thermal: ThermalConfig,
+    pub pressure: PressureConfig,
+    pub systemd: SystemdConfig,
+    pub lock_keys: LockKeysConfig,
+    pub mpris: MprisConfig,
+    pub hotplug: HotplugConfig,
+    pub privacy: PrivacyConfig,
+    pub audio: AudioConfig,
+    pub status: StatusConfig,
+    pub backlight: BacklightConfig,
+    pub osd: OsdConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct BatteryConfig {
+    /// Keep a single "Charging" notification open and update it as the battery charges.
+    pub charging_progress: bool,
+    /// Remind to unplug the charger once charging reaches this percentage.
+    pub unplug_at: Option<u64>,
+    /// Remind to plug in the charger once discharging reaches this percentage.
+    pub plug_in_at: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct PowerProfilesConfig {
+    /// Switch to the power-saver profile when running on battery below this percentage.
+    pub power_saver_below: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CriticalConfig {
+    /// Action taken when the countdown on a critical battery runs out, none by default.
+    pub action: Option<CriticalAction>,
+    pub countdown_secs: u64,
+    /// How much time "Postpone" adds to the countdown.
+    pub postpone_secs: u64,
+}
+
+impl Default for CriticalConfig {
+    fn default() -> Self {
+        Self {
+            action: None,
+            countdown_secs: 60,
+            postpone_secs: 300,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct BluetoothConfig {
+    /// Warn once a connected device's battery drops to this percentage.
+    pub low_battery: u8,
+}
+
+impl Default for BluetoothConfig {
+    fn default() -> Self {
+        Self { low_battery: 20 }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// Mount points to watch, none by default.
+    pub paths: Vec<PathBuf>,
+    pub interval_secs: u64,
+    pub low: SpaceThreshold,
+    pub critical: SpaceThreshold,
+    /// Percentage points of free space a warning needs to clear before it goes quiet.
+    pub hysteresis_percent: f64,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            interval_secs: 60,
+            low: SpaceThreshold {
+                free_percent: Some(10.0),
+                free_bytes: None,
+            },
+            critical: SpaceThreshold {
+                free_percent: Some(5.0),
+                free_bytes: None,
+            },
+            hysteresis_percent: 2.0,
+        }
+    }
+}
+
+/// Reached once free space drops below either limit.
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SpaceThreshold {
+    pub free_percent: Option<f64>,
+    pub free_bytes: Option<u64>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct ThermalConfig {
+    pub interval_secs: u64,
+    /// Warn once any sensor reaches this temperature.
+    pub threshold_celsius: Option<f64>,
+    /// Warn once a sensor is within this many degrees of its critical temperature.
+    pub crit_margin_celsius: Option<f64>,
+    /// Minimum time between two warnings for the same sensor.
+    pub cooldown_secs: u64,
+}
+
+impl Default for ThermalConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 10,
+            threshold_celsius: Some(90.0),
+            crit_margin_celsius: Some(5.0),
+            cooldown_secs: 600,
+        }
+    }
+}
+
+/// Thresholds are the share of time some tasks were stalled, in percent.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PressureConfig {
+    pub memory_percent: Option<f64>,
+    pub cpu_percent: Option<f64>,
+    pub io_percent: Option<f64>,
+    /// Polling interval when the kernel doesn't support PSI triggers.
+    pub interval_secs: u64,
+    pub cooldown_secs: u64,
+    /// Report processes killed by the kernel OOM killer or systemd-oomd.
+    pub oom_kills: bool,
+}
+
+impl Default for PressureConfig {
+    fn default() -> Self {
+        Self {
+            memory_percent: Some(20.0),
+            cpu_percent: None,
+            io_percent: None,
+            interval_secs: 5,
+            cooldown_secs: 300,
+            oom_kills: true,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SystemdConfig {
+    /// Watch units of the user's systemd instance.
+    pub user: bool,
+    /// Watch system units.
+    pub system: bool,
+    /// Command for "Show logs", `-u <unit>` and `--user` are appended as needed.
+    pub log_command: Vec<String>,
+}
+
+impl Default for SystemdConfig {
+    fn default() -> Self {
+        Self {
+            user: true,
+            system: true,
+            log_command: ["xdg-terminal-exec", "journalctl", "--pager-end"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct LockKeysConfig {
+    /// Lock keys to show an OSD for, none by default as it means polling
+    /// sysfs.
+    pub keys: Vec<LockKey>,
+    pub interval_ms: u64,
+    /// How long the OSD stays up.
+    pub timeout_ms: i32,
+}
+
+impl Default for LockKeysConfig {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            interval_ms: 200,
+            timeout_ms: 1500,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct MprisConfig {
+    pub enabled: bool,
+    /// Players to leave alone, matched against the start of the name after
+    /// `org.mpris.MediaPlayer2.`, e.g. "firefox".
+    pub ignore: Vec<String>,
+}
+
+impl Default for MprisConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ignore: Vec::new(),
+        }
+    }
+}
+
+/// Rules are `vendor:product` hex IDs, `*` matches any product.
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct HotplugConfig {
+    pub enabled: bool,
+    /// Only announce matching devices, everything when empty.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl Default for HotplugConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    pub microphone: bool,
+    /// Off by default as it means scanning every process's open files.
+    pub camera: bool,
+    /// How often to look for processes using a camera.
+    pub interval_secs: u64,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            microphone: true,
+            camera: false,
+            interval_secs: 2,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct AudioConfig {
+    /// Sound server to talk to, PipeWire needs the `pipewire` cargo feature.
+    pub backend: BackendKind,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Write every event and the current state as JSON lines to stdout.
+    pub stdout: bool,
+    /// Serve the same lines on `$XDG_RUNTIME_DIR/sysnotifier.sock`.
+    pub socket: bool,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BacklightConfig {
+    /// Off by default since the brightness has to be polled.
+    pub enabled: bool,
+    /// Backlight under `/sys/class/backlight` to watch, the panel's by default.
+    pub device: Option<String>,
+    pub interval_ms: u64,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            device: None,
+            interval_ms: 200,
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct OsdConfig {
+    /// Values shown on the OSD instead of a notification, none by default.
+    pub show: Vec<OsdSource>,
+    /// The FIFO wob reads from, `$XDG_RUNTIME_DIR/wob.sock` by default.
+    pub path: Option<PathBuf>,
+    /// wob style for a muted volume, which is shown as 0 without one.
+    pub muted_style: Option<String>,
+    /// wob style for volume over 100%.
+    pub overflow_style: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// How often battery wear is sampled into the state file.
+    pub record_interval_hours: u64,
+    /// Capacity percentages of the design capacity to warn about once crossed.
+    pub thresholds: Vec<f64>,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            record_interval_hours: 24,
+            thresholds: vec![80.0],
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(config_home.join("sysnotifier").join("config.toml"))
+    }
+
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
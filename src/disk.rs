@@ -0,0 +1,192 @@
+use crate::Event;
+use crate::config::{DiskConfig, SpaceThreshold};
+use serde::Serialize;
+use std::{collections::HashMap, path::PathBuf, sync::mpsc, time::Duration};
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiskLevel {
+    #[default]
+    Normal,
+    Low,
+    Critical,
+}
+
+/// Free space of one configured mount point.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct DiskSpace {
+    pub path: String,
+    pub level: DiskLevel,
+    pub free: u64,
+    pub total: u64,
+}
+
+impl DiskSpace {
+    pub fn free_percent(&self) -> f64 {
+        if self.total == 0 {
+            return 100.0;
+        }
+        self.free as f64 * 100.0 / self.total as f64
+    }
+}
+
+fn statvfs(path: &PathBuf) -> std::io::Result<(u64, u64)> {
+    let stat = rustix::fs::statvfs(path)?;
+    Ok((stat.f_bavail * stat.f_frsize, stat.f_blocks * stat.f_frsize))
+}
+
+/// Whether free space is below `threshold` raised by `margin` percentage points.
+fn below(threshold: &SpaceThreshold, free: u64, total: u64, margin: f64) -> bool {
+    let free_percent = if total == 0 {
+        100.0
+    } else {
+        free as f64 * 100.0 / total as f64
+    };
+    let margin_bytes = (total as f64 * margin / 100.0) as u64;
+
+    threshold
+        .free_percent
+        .is_some_and(|percent| free_percent < percent + margin)
+        || threshold
+            .free_bytes
+            .is_some_and(|bytes| free < bytes.saturating_add(margin_bytes))
+}
+
+/// Escalates as soon as a threshold is crossed but only calms down once free
+/// space is back above the threshold plus the hysteresis band.
+fn level(config: &DiskConfig, free: u64, total: u64, current: DiskLevel) -> DiskLevel {
+    let band = config.hysteresis_percent;
+
+    if below(&config.critical, free, total, 0.0)
+        || (current == DiskLevel::Critical && below(&config.critical, free, total, band))
+    {
+        DiskLevel::Critical
+    } else if below(&config.low, free, total, 0.0)
+        || (current >= DiskLevel::Low && below(&config.low, free, total, band))
+    {
+        DiskLevel::Low
+    } else {
+        DiskLevel::Normal
+    }
+}
+
+fn handle_disk_space(event_sender: &mpsc::Sender<Event>, space: DiskSpace) {
+    _ = event_sender.send(Event::DiskSpace(space));
+}
+
+pub struct DiskMonitor {
+    config: DiskConfig,
+}
+
+impl DiskMonitor {
+    pub fn new(config: DiskConfig) -> Self {
+        Self { config }
+    }
+
+    /// Polls every configured path, sending events only when its level changes.
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) {
+        if self.config.paths.is_empty() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut levels = HashMap::new();
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+
+            loop {
+                interval.tick().await;
+
+                for path in &self.config.paths {
+                    // A hung network filesystem must not stall the runtime.
+                    let stat = tokio::task::spawn_blocking({
+                        let path = path.clone();
+                        move || statvfs(&path)
+                    });
+                    let Ok(Ok((free, total))) = stat.await else {
+                        continue;
+                    };
+
+                    let current = levels.get(path).copied().unwrap_or_default();
+                    let next = level(&self.config, free, total, current);
+                    if next == current {
+                        continue;
+                    }
+
+                    levels.insert(path.clone(), next);
+                    handle_disk_space(
+                        &event_sender,
+                        DiskSpace {
+                            path: path.display().to_string(),
+                            level: next,
+                            free,
+                            total,
+                        },
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOTAL: u64 = 1000;
+
+    #[test]
+    fn levels_escalate_at_once_and_calm_down_past_the_band() {
+        let config = DiskConfig::default();
+        let cases = [
+            (500, DiskLevel::Normal, DiskLevel::Normal),
+            (100, DiskLevel::Normal, DiskLevel::Normal),
+            (99, DiskLevel::Normal, DiskLevel::Low),
+            (49, DiskLevel::Normal, DiskLevel::Critical),
+            (110, DiskLevel::Low, DiskLevel::Low),
+            (120, DiskLevel::Low, DiskLevel::Normal),
+            (49, DiskLevel::Low, DiskLevel::Critical),
+            (60, DiskLevel::Critical, DiskLevel::Critical),
+            (70, DiskLevel::Critical, DiskLevel::Low),
+            (115, DiskLevel::Critical, DiskLevel::Low),
+            (120, DiskLevel::Critical, DiskLevel::Normal),
+        ];
+
+        for (free, current, expected) in cases {
+            assert_eq!(
+                level(&config, free, TOTAL, current),
+                expected,
+                "{free} free from {current:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn byte_thresholds_widen_by_the_band() {
+        let threshold = SpaceThreshold {
+            free_percent: None,
+            free_bytes: Some(100),
+        };
+        let cases = [
+            (99, 0.0, true),
+            (100, 0.0, false),
+            (119, 2.0, true),
+            (120, 2.0, false),
+        ];
+
+        for (free, margin, expected) in cases {
+            assert_eq!(
+                below(&threshold, free, TOTAL, margin),
+                expected,
+                "{free} free with {margin} margin"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_totals_count_as_free() {
+        let config = DiskConfig::default();
+
+        assert_eq!(level(&config, 0, 0, DiskLevel::Low), DiskLevel::Normal);
+    }
+}
@@ -0,0 +1,33 @@
+/// A byte count in decimal units, e.g. "31.9 GB" as shown by file managers.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "kB", "MB", "GB", "TB"];
+
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_use_decimal_units() {
+        let cases = [
+            (0, "0.0 B"),
+            (999, "999.0 B"),
+            (1500, "1.5 kB"),
+            (31_914_983_424, "31.9 GB"),
+            (4_000_000_000_000_000, "4000.0 TB"),
+        ];
+
+        for (size, formatted) in cases {
+            assert_eq!(format_size(size), formatted, "{size}");
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::upower::BatteryHealth;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One battery health sample as stored in the state file.
+#[derive(PartialEq, Debug)]
+pub struct HealthRecord {
+    pub timestamp: u64,
+    pub capacity: f64,
+    pub energy_full: f64,
+    pub energy_full_design: f64,
+    pub charge_cycles: i32,
+    pub technology: String,
+}
+
+impl HealthRecord {
+    pub fn now(health: &BatteryHealth) -> Self {
+        Self {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+            capacity: health.capacity,
+            energy_full: health.energy_full,
+            energy_full_design: health.energy_full_design,
+            charge_cycles: health.charge_cycles,
+            technology: health.technology.into(),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+
+        Some(Self {
+            timestamp: fields.next()?.parse().ok()?,
+            capacity: fields.next()?.parse().ok()?,
+            energy_full: fields.next()?.parse().ok()?,
+            energy_full_design: fields.next()?.parse().ok()?,
+            charge_cycles: fields.next()?.parse().ok()?,
+            technology: fields.next()?.into(),
+        })
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{} {:.2} {:.2} {:.2} {} {}",
+            self.timestamp,
+            self.capacity,
+            self.energy_full,
+            self.energy_full_design,
+            self.charge_cycles,
+            self.technology
+        )
+    }
+}
+
+/// Append-only log of battery health samples under `$XDG_STATE_HOME`.
+pub struct HealthHistory {
+    path: PathBuf,
+}
+
+impl HealthHistory {
+    pub fn new() -> anyhow::Result<Self> {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state"))
+            })
+            .ok_or_else(|| anyhow::anyhow!("Neither XDG_STATE_HOME nor HOME is set"))?;
+
+        Ok(Self {
+            path: state_home.join("sysnotifier").join("battery-health"),
+        })
+    }
+
+    pub fn records(&self) -> anyhow::Result<Vec<HealthRecord>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents.lines().filter_map(HealthRecord::parse).collect()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn last(&self) -> anyhow::Result<Option<HealthRecord>> {
+        Ok(self.records()?.pop())
+    }
+
+    pub fn append(&self, record: &HealthRecord) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", record.line())?;
+
+        Ok(())
+    }
+
+    pub fn print_trend(&self) -> anyhow::Result<()> {
+        let records = self.records()?;
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            println!("No battery health recorded yet in {}", self.path.display());
+            return Ok(());
+        };
+
+        println!(
+            "Battery: {}, design capacity {:.1} Wh",
+            last.technology, last.energy_full_design
+        );
+        println!(
+            "{:<12} {:>9} {:>10} {:>7}",
+            "date", "capacity", "full (Wh)", "cycles"
+        );
+        for record in &records {
+            let cycles = match record.charge_cycles {
+                cycles if cycles >= 0 => cycles.to_string(),
+                _ => "-".into(),
+            };
+            println!(
+                "{:<12} {:>8.1}% {:>10.1} {:>7}",
+                format_date(record.timestamp),
+                record.capacity,
+                record.energy_full,
+                cycles
+            );
+        }
+
+        let days = last.timestamp.saturating_sub(first.timestamp) / 86400;
+        println!(
+            "Change: {:+.1}% over {days} days",
+            last.capacity - first.capacity
+        );
+
+        Ok(())
+    }
+}
+
+/// Thresholds that `capacity` dropped below since the `previous` sample.
+pub fn crossed_thresholds(thresholds: &[f64], previous: f64, capacity: f64) -> Vec<f64> {
+    thresholds
+        .iter()
+        .copied()
+        .filter(|threshold| previous >= *threshold && capacity < *threshold)
+        .collect()
+}
+
+/// Whether a sample taken at `now` comes at least `interval` seconds after
+/// the `previous` one.
+pub fn is_due(previous: Option<&HealthRecord>, now: u64, interval: u64) -> bool {
+    previous.is_none_or(|previous| now.saturating_sub(previous.timestamp) >= interval)
+}
+
+/// Formats a unix timestamp as a UTC `YYYY-MM-DD` date.
+fn format_date(timestamp: u64) -> String {
+    // Civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (timestamp / 86400) as i64 + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64) -> HealthRecord {
+        HealthRecord {
+            timestamp,
+            capacity: 87.46,
+            energy_full: 49.85,
+            energy_full_design: 57.0,
+            charge_cycles: 312,
+            technology: "lithium-ion".into(),
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let line = "1700000000 87.46 49.85 57.00 312 lithium-ion";
+
+        assert_eq!(HealthRecord::parse(line), Some(record(1700000000)));
+        assert_eq!(record(1700000000).line(), line);
+    }
+
+    #[test]
+    fn malformed_records_are_skipped() {
+        for line in [
+            "",
+            "1700000000 87.46 49.85 57.00 312",
+            "yesterday 87.46 49.85 57.00 312 lithium-ion",
+        ] {
+            assert_eq!(HealthRecord::parse(line), None, "{line}");
+        }
+    }
+
+    #[test]
+    fn thresholds_are_crossed_once() {
+        let thresholds = [80.0, 60.0];
+        let cases: [(f64, f64, &[f64]); 6] = [
+            (85.0, 81.0, &[]),
+            (81.0, 79.5, &[80.0]),
+            (79.5, 78.0, &[]),
+            (80.0, 59.0, &[80.0, 60.0]),
+            // Capacity going up again after a calibration.
+            (79.0, 82.0, &[]),
+            // A first sample is compared against a new battery.
+            (100.0, 75.0, &[80.0]),
+        ];
+
+        for (previous, capacity, crossed) in cases {
+            assert_eq!(
+                crossed_thresholds(&thresholds, previous, capacity),
+                crossed,
+                "{previous} -> {capacity}"
+            );
+        }
+    }
+
+    #[test]
+    fn samples_are_due_after_the_interval() {
+        let day = 86400;
+        let previous = record(1700000000);
+
+        assert!(is_due(None, 1700000000, day));
+        assert!(!is_due(Some(&previous), 1700000000 + 3600, day));
+        assert!(is_due(Some(&previous), 1700000000 + day, day));
+        // A clock set backwards doesn't sample on every check.
+        assert!(!is_due(Some(&previous), 1600000000, day));
+    }
+}
@@ -0,0 +1,520 @@
+use crate::Event;
+use crate::config::HotplugConfig;
+use rustix::net::{
+    AddressFamily, RecvFlags, SocketType, bind,
+    netlink::{self, SocketAddrNetlink},
+    recv, socket,
+};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+/// Multicast group of the raw kernel uevents, udev re-broadcasts on group 2.
+const KERNEL_GROUP: u32 = 1;
+
+/// One kernel uevent, e.g. `add@/devices/...` followed by `KEY=value` pairs.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Uevent {
+    pub action: String,
+    pub devpath: String,
+    pub subsystem: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+}
+
+/// Parses a NUL separated kernel uevent payload, `None` for anything else
+/// such as udev's `libudev` messages.
+pub fn parse(payload: &[u8]) -> Option<Uevent> {
+    let payload = std::str::from_utf8(payload).ok()?;
+    let mut fields = payload.split('\0').filter(|field| !field.is_empty());
+
+    let (action, devpath) = fields.next()?.split_once('@')?;
+    let properties: HashMap<_, _> = fields
+        .filter_map(|field| field.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    Some(Uevent {
+        action: action.to_string(),
+        devpath: devpath.to_string(),
+        subsystem: properties.get("SUBSYSTEM")?.clone(),
+        properties,
+    })
+}
+
+/// Ordered by how specific the kind is, the most specific one names the device.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    Usb,
+    Input,
+    Mouse,
+    Keyboard,
+    Audio,
+    Webcam,
+}
+
+impl DeviceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceKind::Usb => "USB device",
+            DeviceKind::Input => "Input device",
+            DeviceKind::Mouse => "Mouse",
+            DeviceKind::Keyboard => "Keyboard",
+            DeviceKind::Audio => "Audio device",
+            DeviceKind::Webcam => "Webcam",
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            DeviceKind::Usb => "drive-removable-media-symbolic",
+            DeviceKind::Input => "input-gaming-symbolic",
+            DeviceKind::Mouse => "input-mouse-symbolic",
+            DeviceKind::Keyboard => "input-keyboard-symbolic",
+            DeviceKind::Audio => "audio-card-symbolic",
+            DeviceKind::Webcam => "camera-web-symbolic",
+        }
+    }
+}
+
+/// A hotplugged device, all uevents below the same USB device are merged into one.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct HotplugDevice {
+    /// Sysfs path of the USB device, or of the device itself when not on USB.
+    pub root: String,
+    pub kind: DeviceKind,
+    pub name: String,
+    /// USB vendor and product ID.
+    pub ids: Option<(u16, u16)>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotplugChange {
+    Added,
+    Removed,
+}
+
+/// Whether a path component names a USB device, e.g. `1-2` or `3-1.4`, but not
+/// one of its interfaces like `1-2:1.0`.
+fn is_usb_device(component: &str) -> bool {
+    let Some((bus, ports)) = component.split_once('-') else {
+        return false;
+    };
+    !bus.is_empty()
+        && bus.chars().all(|c| c.is_ascii_digit())
+        && !ports.is_empty()
+        && ports.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// The sysfs path of the USB device `devpath` belongs to, the innermost one
+/// when plugged into a hub.
+fn usb_root(devpath: &str) -> Option<String> {
+    let components: Vec<_> = devpath.split('/').collect();
+    let index = components.iter().rposition(|c| is_usb_device(c))?;
+    Some(components[..=index].join("/"))
+}
+
+/// Parses a `vendor/product/...` hex ID list as found in `PRODUCT=`.
+fn product_ids(value: &str, skip: usize) -> Option<(u16, u16)> {
+    let mut ids = value.split('/').skip(skip);
+    let vendor = u16::from_str_radix(ids.next()?, 16).ok()?;
+    let product = u16::from_str_radix(ids.next()?, 16).ok()?;
+    Some((vendor, product))
+}
+
+fn read_attribute(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+/// Name of a USB device from its `manufacturer` and `product` attributes.
+fn usb_name(sysfs: &Path, root: &str) -> Option<String> {
+    let root = sysfs.join(root.trim_start_matches('/'));
+    let product = read_attribute(&root.join("product"))?;
+    match read_attribute(&root.join("manufacturer")) {
+        Some(manufacturer) if !product.starts_with(&manufacturer) => {
+            Some(format!("{manufacturer} {product}"))
+        }
+        _ => Some(product),
+    }
+}
+
+fn usb_ids(sysfs: &Path, root: &str) -> Option<(u16, u16)> {
+    let root = sysfs.join(root.trim_start_matches('/'));
+    let vendor = read_attribute(&root.join("idVendor"))?;
+    let product = read_attribute(&root.join("idProduct"))?;
+    Some((
+        u16::from_str_radix(&vendor, 16).ok()?,
+        u16::from_str_radix(&product, 16).ok()?,
+    ))
+}
+
+/// Whether the lowest word of an input capability bitmask has all of `bits` set.
+fn has_bits(bitmask: Option<&str>, bits: u64) -> bool {
+    bitmask
+        .and_then(|bitmask| bitmask.split_whitespace().last())
+        .and_then(|word| u64::from_str_radix(word, 16).ok())
+        .is_some_and(|word| word & bits == bits)
+}
+
+/// Kind and name of the device behind an uevent, `None` for uevents that
+/// don't describe a whole device, e.g. USB interfaces or `event*` nodes.
+fn identify(uevent: &Uevent, sysfs: &Path) -> Option<(DeviceKind, Option<String>)> {
+    let node = uevent.devpath.rsplit('/').next()?;
+    let attribute = |name: &str| {
+        read_attribute(
+            &sysfs
+                .join(uevent.devpath.trim_start_matches('/'))
+                .join(name),
+        )
+    };
+
+    match uevent.subsystem.as_str() {
+        "usb" if uevent.get("DEVTYPE") == Some("usb_device") => Some((DeviceKind::Usb, None)),
+        "input" if uevent.get("NAME").is_some() => {
+            let name = uevent
+                .get("NAME")
+                .map(|name| name.trim_matches('"').to_string());
+            // The same heuristics as udev's input_id: REL_X and REL_Y make a
+            // mouse, Esc and the first rows of keys a keyboard.
+            let kind = if has_bits(uevent.get("REL"), 0b11) {
+                DeviceKind::Mouse
+            } else if has_bits(uevent.get("KEY"), 0xffff_fffe) {
+                DeviceKind::Keyboard
+            } else {
+                DeviceKind::Input
+            };
+            Some((kind, name))
+        }
+        "video4linux" if node.starts_with("video") => Some((DeviceKind::Webcam, attribute("name"))),
+        "sound" if node.starts_with("card") => Some((DeviceKind::Audio, attribute("id"))),
+        _ => None,
+    }
+}
+
+/// The device announced by an `add` uevent, reading names from `sysfs` as the
+/// uevent itself only carries IDs.
+pub fn device(uevent: &Uevent, sysfs: &Path) -> Option<HotplugDevice> {
+    // BlueZ has its own notifications, virtual devices aren't plugged in.
+    if uevent.devpath.contains("/bluetooth/") || uevent.devpath.starts_with("/devices/virtual/") {
+        return None;
+    }
+
+    let (kind, name) = identify(uevent, sysfs)?;
+    let root = usb_root(&uevent.devpath).unwrap_or_else(|| uevent.devpath.clone());
+
+    let ids = match uevent.subsystem.as_str() {
+        "usb" => uevent.get("PRODUCT").and_then(|ids| product_ids(ids, 0)),
+        "input" => uevent.get("PRODUCT").and_then(|ids| product_ids(ids, 1)),
+        _ => None,
+    }
+    .or_else(|| usb_ids(sysfs, &root));
+
+    let name = usb_name(sysfs, &root)
+        .or(name)
+        .or_else(|| ids.map(|(vendor, product)| format!("{vendor:04x}:{product:04x}")))
+        .unwrap_or_else(|| kind.name().to_string());
+
+    Some(HotplugDevice {
+        root,
+        kind,
+        name,
+        ids,
+    })
+}
+
+/// A `vendor:product` rule, `*` matches any product, e.g. `046d:*`.
+fn rule_matches(rule: &str, (vendor, product): (u16, u16)) -> bool {
+    let Some((rule_vendor, rule_product)) = rule.split_once(':') else {
+        return false;
+    };
+    let matches = |rule: &str, id: u16| {
+        rule == "*" || u16::from_str_radix(rule, 16).is_ok_and(|rule| rule == id)
+    };
+    matches(rule_vendor, vendor) && matches(rule_product, product)
+}
+
+/// Exclusions win, an empty include list includes everything.
+fn included(config: &HotplugConfig, ids: Option<(u16, u16)>) -> bool {
+    let Some(ids) = ids else {
+        return config.include.is_empty();
+    };
+    !config.exclude.iter().any(|rule| rule_matches(rule, ids))
+        && (config.include.is_empty() || config.include.iter().any(|rule| rule_matches(rule, ids)))
+}
+
+fn handle_hotplug(
+    event_sender: &mpsc::Sender<Event>,
+    device: HotplugDevice,
+    change: HotplugChange,
+) {
+    _ = event_sender.send(Event::Hotplug(device, change));
+}
+
+/// Merges the uevents of one physical device, tracking the most specific kind
+/// seen for every root. Devices plugged in before startup aren't known, so
+/// their removal goes unannounced.
+struct Tracker {
+    config: HotplugConfig,
+    sysfs: PathBuf,
+    devices: HashMap<String, HotplugDevice>,
+}
+
+impl Tracker {
+    fn handle(&mut self, uevent: &Uevent) -> Option<(HotplugDevice, HotplugChange)> {
+        match uevent.action.as_str() {
+            "add" => {
+                let device = device(uevent, &self.sysfs)?;
+                if !included(&self.config, device.ids) {
+                    return None;
+                }
+                if self
+                    .devices
+                    .get(&device.root)
+                    .is_some_and(|known| known.kind >= device.kind)
+                {
+                    return None;
+                }
+                self.devices.insert(device.root.clone(), device.clone());
+                Some((device, HotplugChange::Added))
+            }
+            // Children go away with their root, only its removal is reported.
+            "remove" => {
+                let device = self.devices.remove(&uevent.devpath)?;
+                Some((device, HotplugChange::Removed))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct HotplugMonitor {
+    config: HotplugConfig,
+}
+
+impl HotplugMonitor {
+    pub fn new(config: HotplugConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let fd = socket(
+            AddressFamily::NETLINK,
+            SocketType::DGRAM,
+            Some(netlink::KOBJECT_UEVENT),
+        )?;
+        bind(&fd, &SocketAddrNetlink::new(0, KERNEL_GROUP))?;
+
+        let mut tracker = Tracker {
+            config: self.config,
+            sysfs: PathBuf::from("/sys"),
+            devices: HashMap::new(),
+        };
+
+        // recv(2) blocks, so the socket gets a thread of its own.
+        std::thread::spawn(move || {
+            let mut buffer = vec![0; 16384];
+            loop {
+                let length = match recv(&fd, &mut buffer[..], RecvFlags::empty()) {
+                    Ok((_, length)) => length.min(buffer.len()),
+                    // The socket buffer overflowed, later uevents are still fine.
+                    Err(rustix::io::Errno::NOBUFS | rustix::io::Errno::INTR) => continue,
+                    Err(_) => return,
+                };
+
+                let Some(uevent) = parse(&buffer[..length]) else {
+                    continue;
+                };
+                if let Some((device, change)) = tracker.handle(&uevent) {
+                    handle_hotplug(&event_sender, device, change);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: &str = "/devices/pci0000:00/0000:00:14.0/usb1/1-2";
+
+    fn payload(fields: &[&str]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        for field in fields {
+            payload.extend_from_slice(field.as_bytes());
+            payload.push(0);
+        }
+        payload
+    }
+
+    fn usb_add() -> Vec<u8> {
+        payload(&[
+            "add@/devices/pci0000:00/0000:00:14.0/usb1/1-2",
+            "ACTION=add",
+            "DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2",
+            "SUBSYSTEM=usb",
+            "MAJOR=189",
+            "MINOR=3",
+            "DEVNAME=bus/usb/001/004",
+            "DEVTYPE=usb_device",
+            "PRODUCT=46d/c31c/6400",
+            "TYPE=0/0/0",
+            "BUSNUM=001",
+            "DEVNUM=004",
+            "SEQNUM=5231",
+        ])
+    }
+
+    fn input_add() -> Vec<u8> {
+        payload(&[
+            "add@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0005/input/input21",
+            "ACTION=add",
+            "DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0/0003:046D:C31C.0005/input/input21",
+            "SUBSYSTEM=input",
+            "PRODUCT=3/46d/c31c/110",
+            "NAME=\"Logitech USB Keyboard\"",
+            "PHYS=\"usb-0000:00:14.0-2/input0\"",
+            "UNIQ=\"\"",
+            "PROP=0",
+            "EV=120013",
+            "KEY=1000000000007 ff9f207ac14057ff febeffdfffefffff fffffffffffffffe",
+            "MSC=10",
+            "LED=1f",
+            "MODALIAS=input:b0003v046DpC31Ce0110-e0,1,4,11,14,k71,72,73,ramlsfw",
+            "SEQNUM=5240",
+        ])
+    }
+
+    fn interface_add() -> Vec<u8> {
+        payload(&[
+            "add@/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0",
+            "ACTION=add",
+            "DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2/1-2:1.0",
+            "SUBSYSTEM=usb",
+            "DEVTYPE=usb_interface",
+            "PRODUCT=46d/c31c/6400",
+            "INTERFACE=3/1/1",
+            "SEQNUM=5232",
+        ])
+    }
+
+    fn usb_remove() -> Vec<u8> {
+        payload(&[
+            "remove@/devices/pci0000:00/0000:00:14.0/usb1/1-2",
+            "ACTION=remove",
+            "DEVPATH=/devices/pci0000:00/0000:00:14.0/usb1/1-2",
+            "SUBSYSTEM=usb",
+            "DEVTYPE=usb_device",
+            "PRODUCT=46d/c31c/6400",
+            "SEQNUM=5260",
+        ])
+    }
+
+    fn tracker(config: HotplugConfig) -> Tracker {
+        Tracker {
+            config,
+            // Nothing to read, names come from the uevents.
+            sysfs: PathBuf::from("/nonexistent"),
+            devices: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn parses_kernel_uevents() {
+        let uevent = parse(&usb_add()).unwrap();
+
+        assert_eq!(uevent.action, "add");
+        assert_eq!(uevent.devpath, ROOT);
+        assert_eq!(uevent.subsystem, "usb");
+        assert_eq!(uevent.get("PRODUCT"), Some("46d/c31c/6400"));
+    }
+
+    #[test]
+    fn ignores_udev_messages() {
+        let mut message = b"libudev\0".to_vec();
+        message.extend_from_slice(&[0xfe, 0xed, 0xca, 0xfe, 0x28, 0, 0, 0]);
+
+        assert_eq!(parse(&message), None);
+    }
+
+    #[test]
+    fn identifies_devices() {
+        let sysfs = Path::new("/nonexistent");
+
+        let usb = device(&parse(&usb_add()).unwrap(), sysfs).unwrap();
+        assert_eq!(usb.root, ROOT);
+        assert_eq!(usb.kind, DeviceKind::Usb);
+        assert_eq!(usb.ids, Some((0x046d, 0xc31c)));
+        assert_eq!(usb.name, "046d:c31c");
+
+        let keyboard = device(&parse(&input_add()).unwrap(), sysfs).unwrap();
+        assert_eq!(keyboard.root, ROOT);
+        assert_eq!(keyboard.kind, DeviceKind::Keyboard);
+        assert_eq!(keyboard.ids, Some((0x046d, 0xc31c)));
+        assert_eq!(keyboard.name, "Logitech USB Keyboard");
+
+        assert_eq!(device(&parse(&interface_add()).unwrap(), sysfs), None);
+    }
+
+    #[test]
+    fn merges_uevents_of_one_device() {
+        let mut tracker = tracker(HotplugConfig::default());
+
+        let (usb, change) = tracker.handle(&parse(&usb_add()).unwrap()).unwrap();
+        assert_eq!((usb.kind, change), (DeviceKind::Usb, HotplugChange::Added));
+        assert_eq!(tracker.handle(&parse(&interface_add()).unwrap()), None);
+
+        let (keyboard, change) = tracker.handle(&parse(&input_add()).unwrap()).unwrap();
+        assert_eq!(
+            (keyboard.kind, change),
+            (DeviceKind::Keyboard, HotplugChange::Added)
+        );
+
+        let (removed, change) = tracker.handle(&parse(&usb_remove()).unwrap()).unwrap();
+        assert_eq!(removed, keyboard);
+        assert_eq!(change, HotplugChange::Removed);
+    }
+
+    #[test]
+    fn applies_include_and_exclude_rules() {
+        let excluded = HotplugConfig {
+            exclude: vec!["046d:*".into()],
+            ..HotplugConfig::default()
+        };
+        assert_eq!(tracker(excluded).handle(&parse(&usb_add()).unwrap()), None);
+
+        let included = HotplugConfig {
+            include: vec!["046d:c31c".into()],
+            ..HotplugConfig::default()
+        };
+        assert!(
+            tracker(included)
+                .handle(&parse(&usb_add()).unwrap())
+                .is_some()
+        );
+
+        let other = HotplugConfig {
+            include: vec!["1050:*".into()],
+            ..HotplugConfig::default()
+        };
+        assert_eq!(tracker(other).handle(&parse(&usb_add()).unwrap()), None);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::Event;
+use crate::config::LockKeysConfig;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, path::Path, sync::mpsc, time::Duration};
+
+#[derive(Deserialize, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockKey {
+    Caps,
+    Num,
+    Scroll,
+}
+
+impl LockKey {
+    /// Suffix of the LED class devices, e.g. `input3::capslock`.
+    fn led_suffix(&self) -> &'static str {
+        match self {
+            LockKey::Caps => "::capslock",
+            LockKey::Num => "::numlock",
+            LockKey::Scroll => "::scrolllock",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LockKey::Caps => "Caps Lock",
+            LockKey::Num => "Num Lock",
+            LockKey::Scroll => "Scroll Lock",
+        }
+    }
+}
+
+/// Whether the lock is on for any keyboard, `None` without a matching LED.
+fn lock_state(key: LockKey) -> Option<bool> {
+    let leds = fs::read_dir("/sys/class/leds").ok()?;
+
+    let mut state = None;
+    for led in leds.flatten() {
+        if !led
+            .file_name()
+            .to_string_lossy()
+            .ends_with(key.led_suffix())
+        {
+            continue;
+        }
+        let Some(brightness) = read_brightness(&led.path().join("brightness")) else {
+            continue;
+        };
+        state = Some(state.unwrap_or(false) || brightness > 0);
+    }
+    state
+}
+
+fn read_brightness(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+fn handle_lock_key(event_sender: &mpsc::Sender<Event>, key: LockKey, on: bool) -> bool {
+    event_sender.send(Event::LockKey(key, on)).is_ok()
+}
+
+pub struct LockKeyMonitor {
+    config: LockKeysConfig,
+}
+
+impl LockKeyMonitor {
+    pub fn new(config: LockKeysConfig) -> Self {
+        Self { config }
+    }
+
+    /// Keyboard LEDs are set by the input layer, which doesn't notify
+    /// readers of `brightness`, and lack `brightness_hw_changed`, so they are
+    /// polled. The state at startup, or once a keyboard is plugged in, isn't
+    /// announced.
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) {
+        if self.config.keys.is_empty() {
+            return;
+        }
+        // Keys without a keyboard LED yet are still watched.
+        let mut states: HashMap<_, _> = self
+            .config
+            .keys
+            .iter()
+            .map(|key| (*key, lock_state(*key)))
+            .collect();
+
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_millis(self.config.interval_ms.max(50)));
+
+            loop {
+                interval.tick().await;
+
+                for (key, state) in states.iter_mut() {
+                    let Some(on) = lock_state(*key) else {
+                        continue;
+                    };
+                    let changed = state.is_some_and(|state| state != on);
+                    *state = Some(on);
+                    if changed && !handle_lock_key(&event_sender, *key, on) {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,150 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use serde::Deserialize;
+use std::sync::mpsc;
+use zbus::{proxy, zvariant::OwnedFd};
+
+/// What to do once the critical battery countdown runs out.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CriticalAction {
+    Suspend,
+    Hibernate,
+    PowerOff,
+}
+
+impl CriticalAction {
+    /// Progressive form used in the countdown, e.g. "Suspending in 60s".
+    pub fn progressive(&self) -> &'static str {
+        match self {
+            CriticalAction::Suspend => "Suspending",
+            CriticalAction::Hibernate => "Hibernating",
+            CriticalAction::PowerOff => "Powering off",
+        }
+    }
+
+    pub fn now_label(&self) -> &'static str {
+        match self {
+            CriticalAction::Suspend => "Suspend now",
+            CriticalAction::Hibernate => "Hibernate now",
+            CriticalAction::PowerOff => "Power off now",
+        }
+    }
+}
+
+#[proxy(
+    interface = "org.freedesktop.login1.Manager",
+    default_service = "org.freedesktop.login1",
+    default_path = "/org/freedesktop/login1"
+)]
+trait Manager {
+    #[zbus(signal)]
+    fn prepare_for_sleep(&self, start: bool) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn prepare_for_shutdown(&self, start: bool) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn lid_closed(&self) -> zbus::Result<bool>;
+
+    fn suspend(&self, interactive: bool) -> zbus::Result<()>;
+
+    fn hibernate(&self, interactive: bool) -> zbus::Result<()>;
+
+    fn power_off(&self, interactive: bool) -> zbus::Result<()>;
+
+    fn inhibit(&self, what: &str, who: &str, why: &str, mode: &str) -> zbus::Result<OwnedFd>;
+}
+
+fn handle_prepare_for_sleep(event_sender: &mpsc::Sender<Event>, start: bool) {
+    _ = event_sender.send(Event::PrepareForSleep(start));
+}
+
+fn handle_prepare_for_shutdown(event_sender: &mpsc::Sender<Event>, start: bool) {
+    _ = event_sender.send(Event::PrepareForShutdown(start));
+}
+
+fn handle_lid_closed(event_sender: &mpsc::Sender<Event>, closed: bool) {
+    _ = event_sender.send(Event::LidClosed(closed));
+}
+
+pub struct LogindManager {
+    connection: zbus::Connection,
+}
+
+impl LogindManager {
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+
+        Ok(Self { connection })
+    }
+
+    pub async fn perform(&self, action: CriticalAction) -> anyhow::Result<()> {
+        let manager = ManagerProxy::new(&self.connection).await?;
+
+        match action {
+            CriticalAction::Suspend => manager.suspend(false).await?,
+            CriticalAction::Hibernate => manager.hibernate(false).await?,
+            CriticalAction::PowerOff => manager.power_off(false).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Delays sleep until the returned lock is dropped, so that the state
+    /// before suspending is recorded while the system is still running.
+    pub async fn inhibit_sleep(&self) -> anyhow::Result<OwnedFd> {
+        let manager = ManagerProxy::new(&self.connection).await?;
+
+        Ok(manager
+            .inhibit(
+                "sleep",
+                "SysNotifier",
+                "Recording the battery level before sleep",
+                "delay",
+            )
+            .await?)
+    }
+
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let manager = ManagerProxy::new(&self.connection).await?;
+
+        {
+            let mut sleep_stream = manager.receive_prepare_for_sleep().await?;
+
+            let event_sender = event_sender.clone();
+            tokio::spawn(async move {
+                while let Some(signal) = sleep_stream.next().await {
+                    if let Ok(args) = signal.args() {
+                        handle_prepare_for_sleep(&event_sender, args.start);
+                    }
+                }
+            });
+        }
+
+        {
+            let mut shutdown_stream = manager.receive_prepare_for_shutdown().await?;
+
+            let event_sender = event_sender.clone();
+            tokio::spawn(async move {
+                while let Some(signal) = shutdown_stream.next().await {
+                    if let Ok(args) = signal.args() {
+                        handle_prepare_for_shutdown(&event_sender, args.start);
+                    }
+                }
+            });
+        }
+
+        let mut lid_stream = manager.receive_lid_closed_changed().await;
+
+        tokio::spawn(async move {
+            while let Some(event) = lid_stream.next().await {
+                if let Ok(closed) = event.get().await {
+                    handle_lid_closed(&event_sender, closed);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
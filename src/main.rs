@@ -1,303 +1,3643 @@
+mod audio;
+mod backlight;
+mod bluetooth;
+mod config;
+mod disk;
+mod format;
+mod health;
+mod hotplug;
+mod leds;
+mod logind;
+mod mpris;
+mod network;
 mod notify;
+mod osd;
+#[cfg(feature = "pipewire")]
+mod pipewire;
+mod power_profiles;
+mod pressure;
+mod privacy;
 mod pulse;
+mod status;
+mod systemd;
+#[cfg(test)]
+mod test_support;
+mod thermal;
+mod udisks;
 mod upower;
 
-use libpulse_binding::context::subscribe::{Facility, InterestMaskSet};
+use audio::{AudioBackend, AudioRouteChange, AudioRoutes, Volume};
+use backlight::BacklightMonitor;
+use bluetooth::{BluetoothDevice, BluetoothManager};
+use config::Config;
+use disk::{DiskLevel, DiskMonitor, DiskSpace};
+use format::format_size;
+use health::{HealthHistory, HealthRecord};
+use hotplug::{HotplugChange, HotplugDevice, HotplugMonitor};
+use leds::{LockKey, LockKeyMonitor};
+use logind::{CriticalAction, LogindManager};
+use mpris::{MprisManager, Track};
+use network::{Connectivity, NetworkConnection, NetworkManager};
 use notify::notify;
-use pulse::Volume;
-use std::collections::HashMap;
+use osd::{Osd, OsdSource};
+use power_profiles::PowerProfilesManager;
+use pressure::{OomdManager, Pressure, PressureMonitor, Resource};
+use privacy::{CameraMonitor, RecordingDevice};
+use status::{Status, StatusEvent, StatusStream};
+use std::collections::{HashMap, HashSet};
 use std::sync::mpsc;
-use upower::{BatteryLevel, BatteryManager, BatteryState};
+use std::time::{Duration, Instant, SystemTime};
+use systemd::{FailedUnit, SystemdManager, UnitScope};
+use thermal::{Sensor, ThermalMonitor};
+use udisks::{StorageChange, StorageDevice, StorageManager};
+use upower::{
+    BatteryHealth, BatteryLevel, BatteryManager, BatterySnapshot, BatteryState, ChargeThreshold,
+};
 
 struct SysNotifier<'a> {
-    pulse: pulse::PulseManager,
+    audio: AudioTracker,
     notifier: Notifier<'a>,
     event_channel: mpsc::Receiver<Event>,
-    last_volume: Option<Volume>,
     battery: BatteryManager,
+    config: Config,
+    unplug_reminded: bool,
+    plug_in_reminded: bool,
+    health: HealthHistory,
+    /// The last health sample taken by this run, standing in for a history
+    /// that can't be read or written.
+    last_health: Option<HealthRecord>,
+    power_profiles: Option<PowerProfilesManager>,
+    /// Profile to restore once plugged in after switching to power-saver.
+    saved_profile: Option<String>,
+    /// When the system went to sleep and the battery state at that moment.
+    sleep_started: Option<(SystemTime, BatterySnapshot)>,
+    event_sender: mpsc::Sender<Event>,
+    logind: Option<LogindManager>,
+    /// logind delay lock held while awake, released once the sleep start
+    /// is recorded.
+    sleep_inhibitor: Option<zbus::zvariant::OwnedFd>,
+    critical_countdown: Option<CriticalCountdown>,
+    /// Bluetooth devices already warned about a low battery.
+    low_bluetooth_batteries: HashSet<String>,
+    storage: Option<StorageManager>,
+    /// Drives powered off from a notification, whose filesystems being
+    /// unmounted and removed is expected, until the drive itself goes away.
+    powered_off_drives: HashSet<String>,
+    systemd: Vec<SystemdManager>,
+    mpris: Option<MprisManager>,
+    status: StatusStream,
+}
+
+/// What was last announced about the sound server, so that only actual
+/// changes turn into notifications.
+struct AudioTracker {
+    backend: Box<dyn AudioBackend>,
+    last_volume: Option<Volume>,
+    /// Default source volume, only its mute state is announced.
+    last_source_volume: Option<Volume>,
+    /// Active card profiles and sink ports, to tell what changed.
+    routes: AudioRoutes,
+    /// Applications last announced as recording from a microphone.
+    microphone_users: Vec<String>,
+    /// Default sink volume and description as last read, for the status
+    /// stream, which shouldn't ask the sound server on every event.
+    sink_volume: Option<Volume>,
+    sink_description: Option<String>,
+}
+
+impl AudioTracker {
+    fn new(mut backend: Box<dyn AudioBackend>) -> anyhow::Result<Self> {
+        let routes = backend.audio_routes()?;
+        let last_source_volume = backend.default_source_volume().ok();
+
+        let mut tracker = Self {
+            backend,
+            last_volume: None,
+            last_source_volume,
+            routes,
+            microphone_users: Vec::new(),
+            sink_volume: None,
+            sink_description: None,
+        };
+        _ = tracker.refresh_sink();
+        Ok(tracker)
+    }
+
+    /// Rereads the default sink, which may have been switched along with its
+    /// volume changing.
+    fn refresh_sink(&mut self) -> anyhow::Result<Volume> {
+        let volume = self.backend.default_sink_volume();
+        self.sink_volume = volume.as_ref().ok().cloned();
+        self.sink_description = self.backend.default_sink_description().ok();
+        volume
+    }
+
+    async fn volume_changed(&mut self, notifier: &mut Notifier<'_>) -> anyhow::Result<()> {
+        let volume = self.refresh_sink()?;
+        if self
+            .last_volume
+            .as_ref()
+            .is_none_or(|last_volume| *last_volume != volume)
+        {
+            notifier.send_volume_notification(&volume).await?;
+            self.last_volume = Some(volume);
+        }
+        Ok(())
+    }
+
+    async fn source_volume_changed(&mut self, notifier: &mut Notifier<'_>) -> anyhow::Result<()> {
+        let volume = self.backend.default_source_volume()?;
+        if self
+            .last_source_volume
+            .as_ref()
+            .is_some_and(|last_volume| last_volume.muted != volume.muted)
+        {
+            notifier
+                .send_microphone_mute_notification(volume.muted)
+                .await?;
+        }
+        self.last_source_volume = Some(volume);
+        Ok(())
+    }
+
+    async fn routes_changed(&mut self, notifier: &mut Notifier<'_>) -> anyhow::Result<()> {
+        let routes = self.backend.audio_routes()?;
+        for change in self.routes.changes(&routes) {
+            notifier.send_audio_route_notification(&change).await?;
+        }
+        self.routes = routes;
+        Ok(())
+    }
+
+    async fn recordings_changed(&mut self, notifier: &mut Notifier<'_>) -> anyhow::Result<()> {
+        let users = self.backend.recording_applications()?;
+        if users != self.microphone_users {
+            notifier
+                .send_recording_notification(RecordingDevice::Microphone, &users)
+                .await?;
+            self.microphone_users = users;
+        }
+        Ok(())
+    }
+
+    /// Forgets the volume after a resume, so the next change is announced
+    /// against the current state rather than the one before sleeping.
+    fn resync(&mut self) {
+        self.last_volume = self.refresh_sink().ok();
+    }
+}
+
+/// Escalation running while the battery is critical, ticking [`Event::CriticalCountdown`].
+struct CriticalCountdown {
+    deadline: Instant,
+    ticker: tokio::task::JoinHandle<()>,
+}
+
+impl CriticalCountdown {
+    fn start(duration: Duration, event_sender: mpsc::Sender<Event>) -> Self {
+        let ticker = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                if event_sender.send(Event::CriticalCountdown).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            deadline: Instant::now() + duration,
+            ticker,
+        }
+    }
+
+    fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+}
+
+impl Drop for CriticalCountdown {
+    fn drop(&mut self) {
+        self.ticker.abort();
+    }
 }
 
 impl SysNotifier<'_> {
-    async fn new() -> anyhow::Result<Self> {
+    async fn new(config: Config) -> anyhow::Result<Self> {
         let (tx, rx) = mpsc::channel();
 
         let mut battery = BatteryManager::new().await?;
         battery.subscribe(tx.clone()).await?;
+        battery.subscribe_health(tx.clone());
 
-        let mut pulse = pulse::PulseManager::new()?;
-        pulse.set_subscription_callback(move |facility, _, _| {
-            let event = match facility {
-                Some(Facility::Sink) => Some(Event::VolumeChanged),
-                Some(Facility::Card) => Some(Event::DefaultDeviceChanged),
-                _ => None,
-            };
+        let power_profiles = match PowerProfilesManager::new().await {
+            Ok(power_profiles) => {
+                power_profiles.subscribe(tx.clone()).await?;
+                Some(power_profiles)
+            }
+            Err(e) => {
+                eprintln!("Power profiles unavailable: {e}");
+                None
+            }
+        };
 
-            if let Some(event) = event {
-                _ = tx.send(event);
+        let logind = match LogindManager::new().await {
+            Ok(logind) => {
+                logind.subscribe(tx.clone()).await?;
+                Some(logind)
             }
-        });
+            Err(e) => {
+                eprintln!("logind unavailable: {e}");
+                None
+            }
+        };
 
-        pulse.subscribe(
-            InterestMaskSet::SERVER | InterestMaskSet::SINK_INPUT | InterestMaskSet::SINK,
-        );
+        match NetworkManager::new().await {
+            Ok(network) => network.subscribe(tx.clone()).await?,
+            Err(e) => eprintln!("NetworkManager unavailable: {e}"),
+        }
+
+        match BluetoothManager::new().await {
+            Ok(bluetooth) => bluetooth.subscribe(tx.clone()).await?,
+            Err(e) => eprintln!("BlueZ unavailable: {e}"),
+        }
+
+        let storage = match StorageManager::new().await {
+            Ok(storage) => {
+                storage.subscribe(tx.clone()).await?;
+                Some(storage)
+            }
+            Err(e) => {
+                eprintln!("UDisks2 unavailable: {e}");
+                None
+            }
+        };
+
+        let mut systemd = Vec::new();
+        let scopes = [
+            (UnitScope::User, config.systemd.user),
+            (UnitScope::System, config.systemd.system),
+        ];
+        for (scope, enabled) in scopes {
+            if !enabled {
+                continue;
+            }
+            match SystemdManager::new(scope).await {
+                Ok(manager) => {
+                    manager.subscribe(tx.clone()).await?;
+                    systemd.push(manager);
+                }
+                Err(e) => eprintln!("systemd ({scope:?}) unavailable: {e}"),
+            }
+        }
+
+        let mpris = if config.mpris.enabled {
+            match MprisManager::new(config.mpris.clone()).await {
+                Ok(mpris) => {
+                    mpris.subscribe(tx.clone()).await?;
+                    Some(mpris)
+                }
+                Err(e) => {
+                    eprintln!("MPRIS unavailable: {e}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        if let Err(e) = HotplugMonitor::new(config.hotplug.clone()).subscribe(tx.clone()) {
+            eprintln!("Hotplug uevents unavailable: {e}");
+        }
+        CameraMonitor::new(config.privacy.clone()).subscribe(tx.clone());
+        LockKeyMonitor::new(config.lock_keys.clone()).subscribe(tx.clone());
+        BacklightMonitor::new(config.backlight.clone()).subscribe(tx.clone());
+        DiskMonitor::new(config.disk.clone()).subscribe(tx.clone());
+        ThermalMonitor::new(config.thermal.clone()).subscribe(tx.clone());
+        PressureMonitor::new(config.pressure.clone()).subscribe(tx.clone());
+
+        if config.pressure.oom_kills {
+            match OomdManager::new().await {
+                Ok(oomd) => oomd.subscribe(tx.clone()).await?,
+                Err(e) => eprintln!("systemd-oomd unavailable: {e}"),
+            }
+        }
+
+        let mut notifier = Notifier::new().await?;
+        notifier.osd = Osd::new(config.osd.clone())?;
+        notifier.subscribe(tx.clone()).await?;
+
+        let event_sender = tx.clone();
+
+        let mut backend = audio::connect(config.audio.backend)?;
+        backend.subscribe(tx.clone(), config.privacy.microphone);
+        let audio = AudioTracker::new(backend)?;
+
+        let status = StatusStream::new(&config.status)?;
 
         Ok(Self {
             battery,
             event_channel: rx,
-            pulse,
-            notifier: Notifier::new().await?,
-            last_volume: None,
+            audio,
+            notifier,
+            config,
+            unplug_reminded: false,
+            plug_in_reminded: false,
+            health: HealthHistory::new()?,
+            last_health: None,
+            power_profiles,
+            saved_profile: None,
+            sleep_started: None,
+            event_sender,
+            logind,
+            sleep_inhibitor: None,
+            critical_countdown: None,
+            low_bluetooth_batteries: HashSet::new(),
+            storage,
+            powered_off_drives: HashSet::new(),
+            systemd,
+            mpris,
+            status,
         })
     }
 
     async fn run(mut self) -> anyhow::Result<()> {
-        let percentage = self.battery.get_battery();
-        loop {
-            match self.event_channel.recv() {
-                Ok(Event::VolumeChanged) => {
-                    let volume = self.pulse.get_default_sink_volume()?;
-                    if self
-                        .last_volume
-                        .as_ref()
-                        .is_none_or(|last_volume| *last_volume != volume)
-                    {
-                        self.notifier.send_volume_notification(&volume).await?;
-                        self.last_volume = Some(volume);
+        self.publish_status(None);
+        self.inhibit_sleep().await;
+        while let Ok(event) = self.event_channel.recv() {
+            // Published once handled, so that the state reflects the event.
+            let published = self.status.enabled().then(|| event.clone());
+            // Sources come and go at runtime, e.g. UPower right after resume
+            // or pipewire-pulse restarting, which must not end the daemon.
+            if let Err(e) = self.handle_event(event).await {
+                eprintln!("Failed to handle event: {e}");
+            }
+            if let Some(event) = published {
+                self.publish_status(Some(&event));
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_event(&mut self, event: Event) -> anyhow::Result<()> {
+        match event {
+            Event::VolumeChanged => {
+                self.audio.volume_changed(&mut self.notifier).await?;
+            }
+            Event::SourceVolumeChanged => {
+                self.audio.source_volume_changed(&mut self.notifier).await?;
+            }
+            Event::AudioRoutesChanged => {
+                self.audio.routes_changed(&mut self.notifier).await?;
+            }
+            Event::BatteryLevel(level) => {
+                let battery = self.battery.snapshot();
+                if self.critical_countdown.is_none() {
+                    self.notifier
+                        .send_battery_level_notification(&battery, &level)
+                        .await?;
+                }
+                self.check_critical(&battery).await?;
+            }
+            Event::BatteryState(state) => {
+                let battery = self.battery.snapshot();
+                let charging_progress = self.config.battery.charging_progress;
+                if charging_progress && state == BatteryState::Charging {
+                    self.notifier
+                        .send_charging_progress_notification(&battery)
+                        .await?;
+                } else {
+                    if charging_progress {
+                        self.notifier.close_charging_progress_notification().await?;
                     }
+                    self.notifier
+                        .send_battery_state_notification(&battery, &state)
+                        .await?;
+                }
+                self.check_charge_limits(&battery).await?;
+                self.check_critical(&battery).await?;
+            }
+            Event::OnBattery(on_battery) => {
+                if on_battery && self.config.battery.charging_progress {
+                    self.notifier.close_charging_progress_notification().await?;
+                }
+                let battery = self.battery.snapshot();
+                self.notifier
+                    .send_power_source_notification(&battery, on_battery)
+                    .await?;
+                self.check_charge_limits(&battery).await?;
+                self.check_power_saver(&battery).await;
+                self.check_critical(&battery).await?;
+            }
+            Event::BatteryPercentage(_) => {
+                let battery = self.battery.snapshot();
+                if self.config.battery.charging_progress && battery.state == BatteryState::Charging
+                {
+                    self.notifier
+                        .send_charging_progress_notification(&battery)
+                        .await?;
+                }
+                self.check_charge_limits(&battery).await?;
+                self.check_power_saver(&battery).await;
+            }
+            Event::ActionInvoked(id, action) => {
+                if !self.notifier.owns(id) {
+                    return Ok(());
                 }
-                Ok(Event::DefaultDeviceChanged) => {
-                    self.notifier.send_device_change_notification().await?;
+                match action.as_str() {
+                    "toggle-charge-threshold" => {
+                        if let Some(threshold) = self.battery.charge_threshold().await {
+                            self.battery
+                                .set_charge_threshold(!threshold.enabled)
+                                .await?;
+                        }
+                        self.notifier.close(id).await?;
+                    }
+                    "critical-now" => self.run_critical_action().await?,
+                    "critical-postpone" => {
+                        if let Some(countdown) = &mut self.critical_countdown {
+                            countdown.deadline +=
+                                Duration::from_secs(self.config.critical.postpone_secs);
+                        }
+                    }
+                    "media-play-pause" | "media-next" => {
+                        let (Some(NotificationKey::NowPlaying(player)), Some(mpris)) =
+                            (self.notifier.key(id).cloned(), &self.mpris)
+                        else {
+                            return Ok(());
+                        };
+                        let result = match action.as_str() {
+                            "media-play-pause" => mpris.play_pause(&player).await,
+                            _ => mpris.next(&player).await,
+                        };
+                        // The player may have quit in the meantime.
+                        if let Err(e) = result {
+                            eprintln!("Failed to control {player}: {e}");
+                        }
+                    }
+                    "unit-logs" | "unit-restart" => {
+                        if let Some(NotificationKey::UnitFailed(scope, name)) =
+                            self.notifier.key(id).cloned()
+                        {
+                            self.handle_unit_action(scope, &name, &action).await?;
+                        }
+                    }
+                    "storage-open" | "storage-unmount" | "storage-power-off" => {
+                        if let Some(NotificationKey::Storage(device)) =
+                            self.notifier.key(id).cloned()
+                        {
+                            self.handle_storage_action(&device, &action).await?;
+                        }
+                    }
+                    _ => {}
                 }
-                Ok(Event::BatteryLevel(level)) => {
+            }
+            Event::CriticalCountdown => {
+                let (Some(countdown), Some(action)) =
+                    (&self.critical_countdown, self.config.critical.action)
+                else {
+                    return Ok(());
+                };
+
+                let remaining = countdown.remaining();
+                if remaining.is_zero() {
+                    self.run_critical_action().await?;
+                } else {
                     self.notifier
-                        .send_battery_level_notification(percentage, &level)
+                        .send_critical_countdown_notification(
+                            &self.battery.snapshot(),
+                            action,
+                            remaining,
+                        )
                         .await?;
                 }
-                Ok(Event::BatteryState(state)) => {
+            }
+            Event::NotificationClosed(id) => self.notifier.forget(id),
+            Event::BatteryHealth => self.record_health().await?,
+            Event::PowerProfile(profile) => {
+                self.notifier
+                    .send_power_profile_notification(&profile)
+                    .await?;
+            }
+            Event::PerformanceDegraded(reason) => {
+                self.notifier
+                    .send_performance_degraded_notification(&reason)
+                    .await?;
+            }
+            Event::PrepareForSleep(true) => {
+                self.sleep_started = Some((SystemTime::now(), self.battery.snapshot()));
+                // Lets the system go to sleep.
+                self.sleep_inhibitor = None;
+            }
+            Event::PrepareForSleep(false) => {
+                self.inhibit_sleep().await;
+                // Property streams may have missed changes while suspended.
+                // UPower is often briefly gone right after resume, the resume
+                // notification shouldn't depend on it.
+                if let Err(e) = self.battery.resync(&self.event_sender).await {
+                    eprintln!("Failed to resync battery after resume: {e}");
+                }
+                self.audio.resync();
+
+                if let Some((started, before)) = self.sleep_started.take() {
+                    let slept = started.elapsed().unwrap_or_default();
                     self.notifier
-                        .send_battery_state_notification(percentage, &state)
+                        .send_resume_notification(slept, &before, &self.battery.snapshot())
                         .await?;
                 }
-                Ok(Event::OnBattery(on_battery)) => {
+            }
+            Event::PrepareForShutdown(start) => {
+                if start {
+                    self.notifier.send_shutdown_notification().await?;
+                }
+            }
+            Event::LidClosed(closed) => {
+                self.notifier.send_lid_notification(closed).await?;
+            }
+            Event::PrimaryConnection(network) => {
+                self.notifier
+                    .send_network_notification(network.as_ref())
+                    .await?;
+            }
+            Event::Connectivity(connectivity) => {
+                self.notifier
+                    .send_connectivity_notification(connectivity)
+                    .await?;
+            }
+            Event::Vpn(name, connected) => {
+                self.notifier
+                    .send_vpn_notification(&name, connected)
+                    .await?;
+            }
+            Event::BluetoothConnected(device) => {
+                self.notifier.send_bluetooth_notification(&device).await?;
+                if !device.connected {
+                    self.low_bluetooth_batteries.remove(&device.alias);
+                }
+            }
+            Event::BluetoothBattery(device) => {
+                let Some(battery) = device.battery else {
+                    return Ok(());
+                };
+                if battery > self.config.bluetooth.low_battery {
+                    self.low_bluetooth_batteries.remove(&device.alias);
+                } else if self.low_bluetooth_batteries.insert(device.alias.clone()) {
                     self.notifier
-                        .send_power_source_notification(percentage, on_battery)
+                        .send_bluetooth_battery_notification(&device)
                         .await?;
                 }
-                Ok(Event::BatteryPercentage(percentage)) => {
-                    self.battery.set_battery(percentage);
+            }
+            Event::Storage(device, change) => {
+                if matches!(change, StorageChange::Unmounted | StorageChange::Removed)
+                    && self.powered_off_drives.contains(&device.drive)
+                {
+                    return Ok(());
                 }
-                Err(_) => break,
+                self.notifier
+                    .send_storage_notification(&device, change)
+                    .await?;
+            }
+            Event::DriveRemoved(drive) => {
+                self.powered_off_drives.remove(&drive);
+                self.notifier.forget_drive(&drive);
+            }
+            Event::DiskSpace(space) => {
+                self.notifier.send_disk_space_notification(&space).await?;
+            }
+            Event::Overheating(sensor) => {
+                self.notifier.send_overheating_notification(&sensor).await?;
+            }
+            Event::FanFailure(fan) => {
+                self.notifier.send_fan_failure_notification(&fan).await?;
+            }
+            Event::Pressure(pressure) => {
+                self.notifier.send_pressure_notification(&pressure).await?;
+            }
+            Event::OomKill(victim) => {
+                self.notifier.send_oom_kill_notification(&victim).await?;
+            }
+            Event::UnitFailed(unit) => {
+                self.notifier.send_unit_failed_notification(&unit).await?;
+            }
+            Event::LockKey(key, on) => {
+                self.notifier
+                    .send_lock_key_notification(key, on, self.config.lock_keys.timeout_ms)
+                    .await?;
+            }
+            Event::NowPlaying(track) => {
+                self.notifier.send_now_playing_notification(&track).await?;
+            }
+            Event::PlayerStopped(player) => {
+                self.notifier
+                    .close_notification(NotificationKey::NowPlaying(player))
+                    .await?;
+            }
+            Event::Hotplug(device, change) => {
+                self.notifier
+                    .send_hotplug_notification(&device, change)
+                    .await?;
+            }
+            Event::Brightness(percentage) => {
+                self.notifier
+                    .send_brightness_notification(percentage)
+                    .await?;
+            }
+            Event::SourceOutputsChanged => {
+                self.audio.recordings_changed(&mut self.notifier).await?;
+            }
+            Event::Recording(device, users) => {
+                self.notifier
+                    .send_recording_notification(device, &users)
+                    .await?;
             }
         }
         Ok(())
     }
-}
 
-struct Notifier<'a> {
-    builder: notify::NotificationBuilder<'a>,
-    active_notifications: HashMap<Event, u32>,
-}
+    /// Samples battery health once `record_interval_hours` passed since the
+    /// last sample, which may have been taken by an earlier run.
+    async fn record_health(&mut self) -> anyhow::Result<()> {
+        let Some(health) = self.battery.health().await else {
+            return Ok(());
+        };
+        // A full disk or read-only state directory only costs the history.
+        let stored = match self.health.last() {
+            Ok(stored) => stored,
+            Err(e) => {
+                eprintln!("Failed to read battery health history: {e}");
+                None
+            }
+        };
+        let previous = [stored.as_ref(), self.last_health.as_ref()]
+            .into_iter()
+            .flatten()
+            .max_by_key(|previous| previous.timestamp);
 
-impl<'a> Notifier<'a> {
-    async fn new() -> anyhow::Result<Self> {
-        Ok(Self {
-            builder: notify().await?,
-            active_notifications: HashMap::new(),
-        })
+        let record = HealthRecord::now(&health);
+        let interval = self.config.health.record_interval_hours.max(1) * 3600;
+        if !health::is_due(previous, record.timestamp, interval) {
+            return Ok(());
+        }
+        // The first sample is compared against a new battery.
+        let previous_capacity = previous.map_or(100.0, |previous| previous.capacity);
+
+        if let Err(e) = self.health.append(&record) {
+            eprintln!("Failed to record battery health: {e}");
+        }
+        let capacity = record.capacity;
+        self.last_health = Some(record);
+
+        for threshold in
+            health::crossed_thresholds(&self.config.health.thresholds, previous_capacity, capacity)
+        {
+            self.notifier
+                .send_battery_health_notification(&health, threshold)
+                .await?;
+        }
+
+        Ok(())
     }
 
-    async fn send_volume_notification(&mut self, volume: &Volume) -> anyhow::Result<()> {
-        let id = *self
-            .active_notifications
-            .get(&Event::VolumeChanged)
-            .unwrap_or(&0);
+    /// Publishes the current state along with `event`, or on its own on
+    /// startup.
+    fn publish_status(&mut self, event: Option<&Event>) {
+        if !self.status.enabled() {
+            return;
+        }
+        let event = match event.map(StatusEvent::new) {
+            Some(None) => return,
+            event => event.flatten(),
+        };
+        let status = Status::new(
+            self.audio.sink_volume.as_ref(),
+            self.audio.sink_description.as_deref(),
+            &self.battery.snapshot(),
+        );
+        if let Err(e) = self.status.send(event.as_ref(), &status) {
+            eprintln!("Failed to publish status: {e}");
+        }
+    }
 
-        let mut builder = self.builder.clone().with_id(id);
-        let volume_summary = format!("Volume [ {}% ]", volume.value);
+    async fn handle_storage_action(
+        &mut self,
+        device: &StorageDevice,
+        action: &str,
+    ) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
 
-        let icon_name = if volume.muted || volume.value == 0 {
-            "audio-volume-muted-symbolic"
-        } else if volume.value < 33 {
-            "audio-volume-low-symbolic"
-        } else if volume.value < 66 {
-            "audio-volume-medium-symbolic"
-        } else {
-            "audio-volume-high-symbolic"
+        let result = match action {
+            "storage-open" => match storage.mount(device).await {
+                Ok(mount_point) => {
+                    tokio::task::spawn_blocking(move || {
+                        std::process::Command::new("xdg-open")
+                            .arg(mount_point)
+                            .status()
+                    });
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            },
+            "storage-unmount" => storage.unmount(device).await,
+            "storage-power-off" => {
+                // Powering off unmounts first, which must not replace the
+                // safe to remove notification.
+                self.powered_off_drives.insert(device.drive.clone());
+                match storage.power_off(device).await {
+                    Ok(()) => {
+                        self.notifier
+                            .send_storage_notification(device, StorageChange::SafeToRemove)
+                            .await?;
+                        Ok(())
+                    }
+                    Err(e) => {
+                        self.powered_off_drives.remove(&device.drive);
+                        Err(e)
+                    }
+                }
+            }
+            _ => Ok(()),
         };
 
-        if volume.muted {
-            builder = builder.with_summary("Volume Muted").with_icon(icon_name);
-        } else {
-            builder = builder
-                .with_summary(&volume_summary)
-                .with_progress(volume.value as i32)
-                .with_icon(icon_name);
+        // Busy filesystems are common, report them instead of bailing out.
+        if let Err(e) = result {
+            let verb = match action {
+                "storage-open" => "open",
+                "storage-unmount" => "unmount",
+                _ => "power off",
+            };
+            self.notifier
+                .send_storage_error_notification(device, verb, &e)
+                .await?;
         }
 
-        let new_id = builder.send().await?;
-        self.active_notifications
-            .insert(Event::VolumeChanged, new_id);
-
         Ok(())
     }
 
-    async fn send_device_change_notification(&mut self) -> anyhow::Result<()> {
-        let id = self
-            .active_notifications
-            .get(&Event::DefaultDeviceChanged)
-            .unwrap_or(&0);
-        let new_id = self
-            .builder
-            .clone()
-            .with_urgency(notify::Urgency::Normal)
-            .with_summary("Device changed")
-            .with_id(*id)
-            .send()
-            .await?;
+    async fn handle_unit_action(
+        &mut self,
+        scope: UnitScope,
+        name: &str,
+        action: &str,
+    ) -> anyhow::Result<()> {
+        match action {
+            "unit-logs" => {
+                let Some((program, args)) = self.config.systemd.log_command.split_first() else {
+                    return Ok(());
+                };
+                let mut command = std::process::Command::new(program);
+                command.args(args);
+                if scope == UnitScope::User {
+                    command.arg("--user");
+                }
+                command.arg("-u").arg(name);
+                tokio::task::spawn_blocking(move || command.status());
+            }
+            "unit-restart" => {
+                let Some(manager) = self.systemd.iter().find(|m| m.scope() == scope) else {
+                    return Ok(());
+                };
+                // Usually a denied polkit prompt, report it instead of bailing out.
+                if let Err(e) = manager.restart(name).await {
+                    self.notifier
+                        .send_unit_restart_error_notification(scope, name, &e)
+                        .await?;
+                }
+            }
+            _ => {}
+        }
 
-        self.active_notifications
-            .insert(Event::DefaultDeviceChanged, new_id);
         Ok(())
     }
 
-    async fn send_battery_state_notification(
-        &mut self,
-        percentage: u64,
-        state: &BatteryState,
-    ) -> anyhow::Result<()> {
-        let id = *self
-            .active_notifications
-            .get(&Event::BatteryState(*state))
-            .unwrap_or(&0);
+    async fn check_critical(&mut self, battery: &BatterySnapshot) -> anyhow::Result<()> {
+        if self.config.critical.action.is_none() {
+            return Ok(());
+        }
 
-        let (summary, icon) = match state {
-            BatteryState::Charging => ("Battery is charging", "battery-charging-symbolic"),
-            BatteryState::Empty => ("Battery is empty", "battery-empty-symbolic"),
-            BatteryState::FullyCharged => {
-                ("Battery fully charged", "battery-full-charged-symbolic")
+        let critical =
+            battery.level == BatteryLevel::Critical || battery.state == BatteryState::Empty;
+        if !battery.on_battery {
+            if self.critical_countdown.take().is_some() {
+                self.notifier
+                    .close_notification(NotificationKey::BatteryLevel(BatteryLevel::Critical))
+                    .await?;
             }
-            _ => return Ok(()),
+        } else if critical && self.critical_countdown.is_none() {
+            self.critical_countdown = Some(CriticalCountdown::start(
+                Duration::from_secs(self.config.critical.countdown_secs),
+                self.event_sender.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Takes the logind sleep delay lock unless already held. Without it, the
+    /// sleep start may only be recorded after resuming.
+    async fn inhibit_sleep(&mut self) {
+        let Some(logind) = &self.logind else {
+            return;
         };
+        if self.sleep_inhibitor.is_some() {
+            return;
+        }
+        match logind.inhibit_sleep().await {
+            Ok(inhibitor) => self.sleep_inhibitor = Some(inhibitor),
+            Err(e) => eprintln!("Failed to delay sleep: {e}"),
+        }
+    }
 
-        let new_id = self
-            .builder
-            .clone()
-            .with_summary(summary)
-            .with_icon(icon)
-            .with_body(&format!("Current battery percentage: {percentage}%"))
-            .with_progress(percentage as i32)
-            .with_id(id)
-            .send()
+    async fn run_critical_action(&mut self) -> anyhow::Result<()> {
+        let Some(action) = self.config.critical.action else {
+            return Ok(());
+        };
+
+        self.critical_countdown = None;
+        self.notifier
+            .close_notification(NotificationKey::BatteryLevel(BatteryLevel::Critical))
             .await?;
 
-        self.active_notifications
-            .insert(Event::BatteryState(*state), new_id);
+        match &self.logind {
+            Some(logind) => logind.perform(action).await?,
+            None => eprintln!("Cannot perform critical battery action without logind"),
+        }
+
         Ok(())
     }
 
-    async fn send_battery_level_notification(
-        &mut self,
-        percentage: u64,
-        level: &BatteryLevel,
-    ) -> anyhow::Result<()> {
-        let id = *self
-            .active_notifications
-            .get(&Event::BatteryLevel(*level))
-            .unwrap_or(&0);
+    /// Failures, e.g. a denied polkit prompt or power-profiles-daemon
+    /// restarting, are logged rather than skipping the other battery checks.
+    async fn check_power_saver(&mut self, battery: &BatterySnapshot) {
+        if let Err(e) = self.switch_power_saver(battery).await {
+            eprintln!("Failed to switch power profile: {e}");
+        }
+    }
 
-        let (summary, icon, urgency) = match level {
-            BatteryLevel::Critical => (
-                "Battery level critical",
-                "battery-caution-symbolic",
-                notify::Urgency::Critical,
+    async fn switch_power_saver(&mut self, battery: &BatterySnapshot) -> anyhow::Result<()> {
+        let (Some(power_profiles), Some(below)) = (
+            &self.power_profiles,
+            self.config.power_profiles.power_saver_below,
+        ) else {
+            return Ok(());
+        };
+
+        if battery.on_battery && battery.percentage < below {
+            if self.saved_profile.is_none() {
+                let profile = power_profiles.active_profile().await?;
+                if profile != "power-saver" {
+                    power_profiles.set_active_profile("power-saver").await?;
+                }
+                self.saved_profile = Some(profile);
+            }
+        } else if !battery.on_battery
+            && let Some(profile) = self.saved_profile.take()
+            // Leave a profile picked by hand while on battery alone.
+            && power_profiles.active_profile().await? == "power-saver"
+        {
+            power_profiles.set_active_profile(&profile).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_charge_limits(&mut self, battery: &BatterySnapshot) -> anyhow::Result<()> {
+        if let Some(unplug_at) = self.config.battery.unplug_at {
+            let due = battery.state == BatteryState::Charging && battery.percentage >= unplug_at;
+            match remind(&mut self.unplug_reminded, due) {
+                Some(true) => {
+                    let threshold = self.battery.charge_threshold().await;
+                    self.notifier
+                        .send_unplug_reminder(battery, threshold)
+                        .await?;
+                }
+                Some(false) => {
+                    self.notifier
+                        .close_notification(NotificationKey::UnplugReminder)
+                        .await?;
+                }
+                None => {}
+            }
+        }
+
+        if let Some(plug_in_at) = self.config.battery.plug_in_at {
+            let due = battery.on_battery && battery.percentage <= plug_in_at;
+            match remind(&mut self.plug_in_reminded, due) {
+                Some(true) => {
+                    self.notifier.send_plug_in_reminder(battery).await?;
+                }
+                Some(false) => {
+                    self.notifier
+                        .close_notification(NotificationKey::PlugInReminder)
+                        .await?;
+                }
+                None => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Notifier<'a> {
+    builder: notify::NotificationBuilder<'a>,
+    active_notifications: HashMap<NotificationKey, u32>,
+    /// Shows configured values on a bar OSD instead.
+    osd: Option<Osd>,
+}
+
+impl<'a> Notifier<'a> {
+    async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::session().await?;
+        let notifier = Self::with_connection(connection);
+        notifier.builder.watch_server().await?;
+
+        Ok(notifier)
+    }
+
+    fn with_connection(connection: zbus::Connection) -> Self {
+        Self {
+            builder: notify(connection),
+            active_notifications: HashMap::new(),
+            osd: None,
+        }
+    }
+
+    async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        self.builder.subscribe(event_sender).await
+    }
+
+    fn owns(&self, id: u32) -> bool {
+        self.active_notifications
+            .values()
+            .any(|active| *active == id)
+    }
+
+    /// Stops tracking a notification that was dismissed or expired, so that
+    /// keys of devices long gone don't pile up.
+    fn forget(&mut self, id: u32) {
+        self.active_notifications.retain(|_, active| *active != id);
+    }
+
+    /// The key a notification we sent is tracked under.
+    fn key(&self, id: u32) -> Option<&NotificationKey> {
+        self.active_notifications
+            .iter()
+            .find(|(_, active)| **active == id)
+            .map(|(key, _)| key)
+    }
+
+    async fn close(&mut self, id: u32) -> anyhow::Result<()> {
+        self.active_notifications.retain(|_, active| *active != id);
+        self.builder.close(id).await?;
+        Ok(())
+    }
+
+    /// Whether `source` went to the OSD, falling back to a notification
+    /// when nothing reads it.
+    fn show_on_osd(
+        &self,
+        source: OsdSource,
+        show: impl FnOnce(&Osd) -> anyhow::Result<()>,
+    ) -> bool {
+        self.osd
+            .as_ref()
+            .is_some_and(|osd| osd.shows(source) && show(osd).is_ok())
+    }
+
+    async fn send_volume_notification(&mut self, volume: &Volume) -> anyhow::Result<()> {
+        if self.show_on_osd(OsdSource::Volume, |osd| osd.show_volume(volume)) {
+            return Ok(());
+        }
+
+        let key = NotificationKey::Volume;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let mut builder = self.builder.clone().with_id(id);
+        let volume_summary = format!("Volume [ {}% ]", volume.value);
+
+        let icon_name = if volume.muted || volume.value == 0 {
+            "audio-volume-muted-symbolic"
+        } else if volume.value < 33 {
+            "audio-volume-low-symbolic"
+        } else if volume.value < 66 {
+            "audio-volume-medium-symbolic"
+        } else {
+            "audio-volume-high-symbolic"
+        };
+
+        if volume.muted {
+            builder = builder.with_summary("Volume Muted").with_icon(icon_name);
+        } else {
+            builder = builder
+                .with_summary(&volume_summary)
+                .with_progress(volume.value as i32)
+                .with_icon(icon_name);
+        }
+
+        let new_id = builder.send().await?;
+        self.active_notifications.insert(key, new_id);
+
+        Ok(())
+    }
+
+    async fn send_brightness_notification(&mut self, percentage: u32) -> anyhow::Result<()> {
+        if self.show_on_osd(OsdSource::Brightness, |osd| osd.show_brightness(percentage)) {
+            return Ok(());
+        }
+
+        let key = NotificationKey::Brightness;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_id(id)
+            .with_summary(&format!("Brightness [ {percentage}% ]"))
+            .with_progress(percentage as i32)
+            .with_icon("display-brightness-symbolic")
+            .send()
+            .await?;
+        self.active_notifications.insert(key, new_id);
+
+        Ok(())
+    }
+
+    async fn send_microphone_mute_notification(&mut self, muted: bool) -> anyhow::Result<()> {
+        let key = NotificationKey::Microphone;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, icon) = match muted {
+            true => ("Microphone Muted", "microphone-sensitivity-muted-symbolic"),
+            false => ("Microphone On", "audio-input-microphone-symbolic"),
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_id(id)
+            .with_summary(summary)
+            .with_icon(icon)
+            .send()
+            .await?;
+        self.active_notifications.insert(key, new_id);
+
+        Ok(())
+    }
+
+    async fn send_lock_key_notification(
+        &mut self,
+        lock_key: LockKey,
+        on: bool,
+        timeout: i32,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::LockKey(lock_key);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let state = if on { "on" } else { "off" };
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{} {state}", lock_key.name()))
+            .with_icon("input-keyboard-symbolic")
+            .with_timeout(timeout)
+            .with_id(id)
+            .send()
+            .await?;
+        self.active_notifications.insert(key, new_id);
+
+        Ok(())
+    }
+
+    async fn send_now_playing_notification(&mut self, track: &Track) -> anyhow::Result<()> {
+        let key = NotificationKey::NowPlaying(track.player.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let mut body = [track.artist.as_str(), track.album.as_str()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" — ");
+        if body.is_empty() {
+            body = track.identity.clone();
+        }
+        let summary = match track.playing {
+            true => track.title.clone(),
+            false => format!("{} (paused)", track.title),
+        };
+
+        let mut builder = self
+            .builder
+            .clone()
+            .with_summary(&summary)
+            .with_body(&body)
+            .with_icon("audio-x-generic-symbolic")
+            .with_action(
+                "media-play-pause",
+                if track.playing { "Pause" } else { "Play" },
+            )
+            .with_action("media-next", "Next")
+            .with_id(id);
+        if let Some(art) = &track.art {
+            builder = builder.with_image(art);
+        }
+
+        let new_id = builder.send().await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    /// Stays up while `users` are recording and closes once they're done.
+    async fn send_recording_notification(
+        &mut self,
+        device: RecordingDevice,
+        users: &[String],
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::Recording(device);
+        if users.is_empty() {
+            return self.close_notification(key).await;
+        }
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{} in use by {}", device.name(), users.join(", ")))
+            .with_icon(device.icon())
+            .with_urgency(notify::Urgency::Normal)
+            .with_timeout(0)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_audio_route_notification(
+        &mut self,
+        change: &AudioRouteChange,
+    ) -> anyhow::Result<()> {
+        // Cards and sinks each keep their own notification.
+        let key = match change {
+            AudioRouteChange::Profile(card, _) => NotificationKey::AudioProfile(*card),
+            AudioRouteChange::Port { sink, .. } => NotificationKey::AudioPort(*sink),
+        };
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, body, icon) = match change {
+            AudioRouteChange::Profile(_, profile) if profile.is_headset() => (
+                format!("Switched to {} — reduced quality", profile.description),
+                profile.card.clone(),
+                "audio-headset-symbolic",
             ),
-            BatteryLevel::Low => (
-                "Battery level low",
-                "battery-low-symbolic",
-                notify::Urgency::Normal,
+            AudioRouteChange::Profile(_, profile) => (
+                format!("Switched to {}", profile.description),
+                profile.card.clone(),
+                "audio-card-symbolic",
             ),
-            BatteryLevel::Normal => (
-                "Battery level normal",
-                "battery-good-symbolic",
-                notify::Urgency::Low,
+            AudioRouteChange::Port { current, .. } if current.is_headphones() => (
+                "Headphones plugged in".to_string(),
+                current.sink.clone(),
+                "audio-headphones-symbolic",
             ),
-            BatteryLevel::High => (
-                "Battery level high",
-                "battery-full-symbolic",
-                notify::Urgency::Low,
+            AudioRouteChange::Port {
+                previous, current, ..
+            } if previous.is_headphones() => (
+                "Headphones unplugged".to_string(),
+                format!("Switched to {}", current.description),
+                "audio-speakers-symbolic",
             ),
-            BatteryLevel::Full => (
-                "Battery level full",
-                "battery-full-charged-symbolic",
-                notify::Urgency::Low,
+            AudioRouteChange::Port { current, .. } => (
+                format!("Switched to {}", current.description),
+                current.sink.clone(),
+                "audio-speakers-symbolic",
             ),
-            _ => return Ok(()),
         };
 
         let new_id = self
             .builder
             .clone()
-            .with_summary(summary)
+            .with_urgency(notify::Urgency::Normal)
+            .with_summary(&summary)
+            .with_body(&body)
             .with_icon(icon)
-            .with_urgency(urgency)
-            .with_body(&format!("Current battery percentage: {percentage}%"))
-            .with_progress(percentage as i32)
             .with_id(id)
             .send()
             .await?;
 
-        self.active_notifications
-            .insert(Event::BatteryLevel(*level), new_id);
+        self.active_notifications.insert(key, new_id);
         Ok(())
     }
 
-    async fn send_power_source_notification(
+    async fn send_battery_state_notification(
         &mut self,
-        percentage: u64,
-        on_battery: bool,
+        battery: &BatterySnapshot,
+        state: &BatteryState,
     ) -> anyhow::Result<()> {
-        let id = *self
-            .active_notifications
-            .get(&Event::OnBattery(on_battery))
-            .unwrap_or(&0);
+        let key = NotificationKey::BatteryState(*state);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
 
-        let (summary, icon) = if on_battery {
-            ("Running on battery power", "battery-symbolic")
-        } else {
-            ("Connected to power", "ac-adapter-symbolic")
+        let (summary, icon) = match state {
+            BatteryState::Charging => ("Battery is charging", "battery-charging-symbolic"),
+            BatteryState::Empty => ("Battery is empty", "battery-empty-symbolic"),
+            BatteryState::FullyCharged => {
+                ("Battery fully charged", "battery-full-charged-symbolic")
+            }
+            _ => return Ok(()),
         };
 
         let new_id = self
             .builder
             .clone()
             .with_summary(summary)
-            .with_body(&format!("Current battery percentage: {percentage}%"))
-            .with_progress(percentage as i32)
             .with_icon(icon)
+            .with_body(&battery_body(battery))
+            .with_progress(battery.percentage as i32)
             .with_id(id)
             .send()
             .await?;
 
-        self.active_notifications
-            .insert(Event::OnBattery(on_battery), new_id);
+        self.active_notifications.insert(key, new_id);
         Ok(())
     }
-}
 
-#[derive(PartialEq, Eq, Hash)]
-enum Event {
-    VolumeChanged,
-    DefaultDeviceChanged,
-    BatteryState(BatteryState),
-    BatteryLevel(BatteryLevel),
-    OnBattery(bool),
-    BatteryPercentage(u64),
-}
+    async fn send_charging_progress_notification(
+        &mut self,
+        battery: &BatterySnapshot,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::BatteryState(BatteryState::Charging);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let sysnotifier = SysNotifier::new().await?;
-    sysnotifier.run().await?;
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Charging")
+            .with_icon("battery-charging-symbolic")
+            .with_body(&battery_body(battery))
+            .with_progress(battery.percentage as i32)
+            .with_timeout(0)
+            .with_id(id)
+            .send()
+            .await?;
 
-    Ok(())
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn close_charging_progress_notification(&mut self) -> anyhow::Result<()> {
+        if let Some(id) = self
+            .active_notifications
+            .remove(&NotificationKey::BatteryState(BatteryState::Charging))
+        {
+            self.builder.close(id).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_unplug_reminder(
+        &mut self,
+        battery: &BatterySnapshot,
+        threshold: Option<ChargeThreshold>,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::UnplugReminder;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let builder = self
+            .builder
+            .clone()
+            .with_icon("battery-full-charging-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_progress(battery.percentage as i32)
+            .with_id(id);
+
+        // Batteries with a hardware charge limit can stop charging by themselves,
+        // so offer to toggle it rather than asking to pull the plug.
+        let new_id = match threshold {
+            Some(threshold) => {
+                let body = format!(
+                    "Battery at {}%. Hardware charge limit ({}–{}%) is {}.",
+                    battery.percentage,
+                    threshold.start,
+                    threshold.end,
+                    if threshold.enabled { "on" } else { "off" },
+                );
+                let label = if threshold.enabled {
+                    "Disable charge limit"
+                } else {
+                    "Enable charge limit"
+                };
+                builder
+                    .with_summary("Charge limit reached")
+                    .with_body(&body)
+                    .with_action("toggle-charge-threshold", label)
+                    .send()
+                    .await?
+            }
+            None => {
+                let body = format!(
+                    "Battery at {}%, unplug the charger to preserve battery health",
+                    battery.percentage
+                );
+                builder
+                    .with_summary("Unplug the charger")
+                    .with_body(&body)
+                    .send()
+                    .await?
+            }
+        };
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_plug_in_reminder(&mut self, battery: &BatterySnapshot) -> anyhow::Result<()> {
+        let key = NotificationKey::PlugInReminder;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Plug in the charger")
+            .with_body(&format!(
+                "Battery at {}%, plug in the charger to preserve battery health",
+                battery.percentage
+            ))
+            .with_icon("battery-low-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_progress(battery.percentage as i32)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_battery_health_notification(
+        &mut self,
+        health: &BatteryHealth,
+        threshold: f64,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::BatteryHealth;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let mut body = format!(
+            "Capacity is {:.1}% of design ({:.1} of {:.1} Wh)",
+            health.capacity, health.energy_full, health.energy_full_design
+        );
+        if health.charge_cycles >= 0 {
+            body += &format!(", {} charge cycles", health.charge_cycles);
+        }
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("Battery health below {threshold}%"))
+            .with_body(&body)
+            .with_icon("battery-caution-symbolic")
+            .with_urgency(notify::Urgency::Low)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_critical_countdown_notification(
+        &mut self,
+        battery: &BatterySnapshot,
+        action: CriticalAction,
+        remaining: Duration,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::BatteryLevel(BatteryLevel::Critical);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!(
+                "{} in {}s",
+                action.progressive(),
+                remaining.as_secs().max(1)
+            ))
+            .with_body(&format!(
+                "Battery critical at {}%, connect the charger",
+                battery.percentage
+            ))
+            .with_icon("battery-caution-symbolic")
+            .with_urgency(notify::Urgency::Critical)
+            .with_progress(battery.percentage as i32)
+            .with_action("critical-now", action.now_label())
+            .with_action("critical-postpone", "Postpone")
+            .with_timeout(0)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_network_notification(
+        &mut self,
+        network: Option<&NetworkConnection>,
+    ) -> anyhow::Result<()> {
+        // Connecting and disconnecting share one notification.
+        let key = NotificationKey::Network;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let builder = self.builder.clone().with_id(id);
+        let new_id = match network {
+            Some(network) => {
+                let name = network.ssid.as_deref().unwrap_or(&network.id);
+                let icon = match (network.kind.as_str(), network.strength) {
+                    ("802-11-wireless", Some(strength)) => match strength {
+                        0..20 => "network-wireless-signal-none-symbolic",
+                        20..40 => "network-wireless-signal-weak-symbolic",
+                        40..60 => "network-wireless-signal-ok-symbolic",
+                        60..80 => "network-wireless-signal-good-symbolic",
+                        _ => "network-wireless-signal-excellent-symbolic",
+                    },
+                    ("802-11-wireless", None) => "network-wireless-symbolic",
+                    ("802-3-ethernet", _) => "network-wired-symbolic",
+                    _ => "network-transmit-receive-symbolic",
+                };
+                let body = network
+                    .strength
+                    .map(|strength| format!("Signal strength {strength}%"))
+                    .unwrap_or_default();
+
+                builder
+                    .with_summary(&format!("Connected to {name}"))
+                    .with_body(&body)
+                    .with_icon(icon)
+                    .send()
+                    .await?
+            }
+            None => {
+                builder
+                    .with_summary("Disconnected")
+                    .with_body("No network connection")
+                    .with_icon("network-offline-symbolic")
+                    .with_urgency(notify::Urgency::Normal)
+                    .send()
+                    .await?
+            }
+        };
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_connectivity_notification(
+        &mut self,
+        connectivity: Connectivity,
+    ) -> anyhow::Result<()> {
+        let (summary, body) = match connectivity {
+            Connectivity::Portal => (
+                "Sign in to network",
+                "A captive portal is blocking internet access",
+            ),
+            Connectivity::Limited => ("Limited connectivity", "Connected without internet access"),
+            Connectivity::Full => {
+                self.close_notification(NotificationKey::Connectivity(Connectivity::Portal))
+                    .await?;
+                self.close_notification(NotificationKey::Connectivity(Connectivity::Limited))
+                    .await?;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+
+        let key = NotificationKey::Connectivity(connectivity);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(summary)
+            .with_body(body)
+            .with_icon("network-error-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_vpn_notification(&mut self, name: &str, connected: bool) -> anyhow::Result<()> {
+        // A VPN going down replaces its "connected" notification.
+        let key = NotificationKey::Vpn(name.into());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, icon) = if connected {
+            (format!("VPN {name} connected"), "network-vpn-symbolic")
+        } else {
+            (
+                format!("VPN {name} disconnected"),
+                "network-vpn-disconnected-symbolic",
+            )
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&summary)
+            .with_icon(icon)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_bluetooth_notification(
+        &mut self,
+        device: &BluetoothDevice,
+    ) -> anyhow::Result<()> {
+        // Connecting and disconnecting the same device share one notification.
+        let key = NotificationKey::Bluetooth(device.alias.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let summary = match (device.connected, device.battery) {
+            (true, Some(battery)) => format!("{} connected — {battery}%", device.alias),
+            (true, None) => format!("{} connected", device.alias),
+            (false, _) => format!("{} disconnected", device.alias),
+        };
+        let icon = if device.icon.is_empty() {
+            "bluetooth-active-symbolic"
+        } else {
+            &device.icon
+        };
+
+        let mut builder = self
+            .builder
+            .clone()
+            .with_summary(&summary)
+            .with_icon(icon)
+            .with_id(id);
+        if let (true, Some(battery)) = (device.connected, device.battery) {
+            builder = builder.with_progress(battery as i32);
+        }
+
+        let new_id = builder.send().await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_bluetooth_battery_notification(
+        &mut self,
+        device: &BluetoothDevice,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::BluetoothBattery(device.alias.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let battery = device.battery.unwrap_or_default();
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{} battery low", device.alias))
+            .with_body(&format!("Battery at {battery}%"))
+            .with_icon("battery-low-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_progress(battery as i32)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_storage_notification(
+        &mut self,
+        device: &StorageDevice,
+        change: StorageChange,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::storage(device);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, body, icon) = match change {
+            StorageChange::Inserted => (
+                format!("{} inserted", device.label),
+                format!("{} ({})", device.device, format_size(device.size)),
+                "drive-removable-media-symbolic",
+            ),
+            StorageChange::Mounted => (
+                format!("{} mounted", device.label),
+                format!(
+                    "Mounted at {}",
+                    device.mount_point.as_deref().unwrap_or_default()
+                ),
+                "drive-removable-media-symbolic",
+            ),
+            StorageChange::Unmounted => (
+                format!("{} unmounted", device.label),
+                device.device.clone(),
+                "media-eject-symbolic",
+            ),
+            StorageChange::SafeToRemove => (
+                format!("{} can be safely removed", device.label),
+                device.device.clone(),
+                "media-eject-symbolic",
+            ),
+            StorageChange::Removed => (
+                format!("{} removed", device.label),
+                device.device.clone(),
+                "media-eject-symbolic",
+            ),
+        };
+
+        let mut builder = self
+            .builder
+            .clone()
+            .with_summary(&summary)
+            .with_body(&body)
+            .with_icon(icon)
+            .with_id(id);
+        if matches!(
+            change,
+            StorageChange::Inserted | StorageChange::Mounted | StorageChange::Unmounted
+        ) {
+            builder = builder.with_action("storage-open", "Open");
+            if device.mount_point.is_some() {
+                builder = builder.with_action("storage-unmount", "Unmount");
+            }
+            builder = builder.with_action("storage-power-off", "Power off");
+        }
+
+        let new_id = builder.send().await?;
+
+        // Inserting the filesystem again later gets a notification of its own.
+        match change {
+            StorageChange::Removed => self.active_notifications.remove(&key),
+            _ => self.active_notifications.insert(key, new_id),
+        };
+        Ok(())
+    }
+
+    /// Stops tracking the filesystems of a drive that went away, including
+    /// those whose removal wasn't announced after powering it off.
+    fn forget_drive(&mut self, drive: &str) {
+        self.active_notifications.retain(
+            |key, _| !matches!(key, NotificationKey::Storage(device) if device.drive == drive),
+        );
+    }
+
+    async fn send_hotplug_notification(
+        &mut self,
+        device: &HotplugDevice,
+        change: HotplugChange,
+    ) -> anyhow::Result<()> {
+        // Connecting a device reveals its kind piece by piece, keep updating
+        // one notification per physical device.
+        let key = NotificationKey::Hotplug(device.root.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let verb = match change {
+            HotplugChange::Added => "connected",
+            HotplugChange::Removed => "disconnected",
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{} {verb}", device.kind.name()))
+            .with_body(&device.name)
+            .with_icon(device.kind.icon())
+            .with_id(id)
+            .send()
+            .await?;
+
+        // A device plugged in again later gets a notification of its own.
+        match change {
+            HotplugChange::Added => self.active_notifications.insert(key, new_id),
+            HotplugChange::Removed => self.active_notifications.remove(&key),
+        };
+        Ok(())
+    }
+
+    async fn send_storage_error_notification(
+        &mut self,
+        device: &StorageDevice,
+        verb: &str,
+        error: &anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::storage(device);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("Failed to {verb} {}", device.label))
+            .with_body(&error.to_string())
+            .with_icon("dialog-error-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_disk_space_notification(&mut self, space: &DiskSpace) -> anyhow::Result<()> {
+        // One notification per mount point, escalating in place.
+        let key = NotificationKey::DiskSpace(space.path.clone());
+
+        let (summary, urgency, timeout) = match space.level {
+            DiskLevel::Normal => return self.close_notification(key).await,
+            DiskLevel::Low => ("Low disk space", notify::Urgency::Normal, -1),
+            DiskLevel::Critical => ("Disk almost full", notify::Urgency::Critical, 0),
+        };
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{summary} on {}", space.path))
+            .with_body(&format!(
+                "{} free of {} ({:.1}%)",
+                format_size(space.free),
+                format_size(space.total),
+                space.free_percent()
+            ))
+            .with_icon("drive-harddisk-symbolic")
+            .with_urgency(urgency)
+            .with_progress(100 - space.free_percent().round() as i32)
+            .with_timeout(timeout)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_overheating_notification(&mut self, sensor: &Sensor) -> anyhow::Result<()> {
+        let key = NotificationKey::Overheating(sensor.name.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let body = match sensor.crit_celsius() {
+            Some(crit) => format!("{:.1} °C, critical at {crit:.1} °C", sensor.celsius()),
+            None => format!("{:.1} °C", sensor.celsius()),
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("{} is running hot", sensor.name))
+            .with_body(&body)
+            .with_icon("sensors-temperature-symbolic")
+            .with_urgency(notify::Urgency::Critical)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_fan_failure_notification(&mut self, fan: &str) -> anyhow::Result<()> {
+        let key = NotificationKey::FanFailure(fan.into());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Fan failure")
+            .with_body(&format!("{fan} reports a fault"))
+            .with_icon("sensors-fan-symbolic")
+            .with_urgency(notify::Urgency::Critical)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_pressure_notification(&mut self, pressure: &Pressure) -> anyhow::Result<()> {
+        let key = NotificationKey::Pressure(pressure.resource);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let mut body = format!("Tasks stalled {}% of the last 10 seconds", pressure.avg10);
+        if !pressure.top.is_empty() {
+            let top: Vec<_> = pressure
+                .top
+                .iter()
+                .map(|process| format!("{} ({})", process.name, format_size(process.rss * 1024)))
+                .collect();
+            body.push_str(&format!("\nTop consumers: {}", top.join(", ")));
+        }
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!(
+                "High {} pressure",
+                pressure.resource.name().to_lowercase()
+            ))
+            .with_body(&body)
+            .with_icon("dialog-warning-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_oom_kill_notification(&mut self, victim: &str) -> anyhow::Result<()> {
+        let key = NotificationKey::OomKill(victim.into());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Out of memory")
+            .with_body(&format!("{victim} was killed to free memory"))
+            .with_icon("dialog-error-symbolic")
+            .with_urgency(notify::Urgency::Critical)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_unit_failed_notification(&mut self, unit: &FailedUnit) -> anyhow::Result<()> {
+        let key = NotificationKey::UnitFailed(unit.scope, unit.name.clone());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let mut body = format!("Result: {}", unit.result);
+        if let Some(status) = unit.exit_status {
+            body.push_str(&format!(", exit status {status}"));
+        }
+        let summary = match unit.scope {
+            UnitScope::User => format!("{} failed", unit.name),
+            UnitScope::System => format!("System unit {} failed", unit.name),
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&summary)
+            .with_body(&body)
+            .with_icon("dialog-error-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_action("unit-logs", "Show logs")
+            .with_action("unit-restart", "Restart")
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_unit_restart_error_notification(
+        &mut self,
+        scope: UnitScope,
+        name: &str,
+        error: &anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::UnitFailed(scope, name.into());
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!("Failed to restart {name}"))
+            .with_body(&error.to_string())
+            .with_icon("dialog-error-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_action("unit-logs", "Show logs")
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_power_profile_notification(&mut self, profile: &str) -> anyhow::Result<()> {
+        let key = NotificationKey::PowerProfile;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!(
+                "Power profile: {}",
+                power_profiles::profile_name(profile)
+            ))
+            .with_icon(&format!("power-profile-{profile}-symbolic"))
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    /// Closes the notification once no longer degraded, with an empty reason.
+    async fn send_performance_degraded_notification(&mut self, reason: &str) -> anyhow::Result<()> {
+        let key = NotificationKey::PerformanceDegraded;
+        if reason.is_empty() {
+            return self.close_notification(key).await;
+        }
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Performance mode degraded")
+            .with_body(power_profiles::degraded_reason(reason))
+            .with_icon("power-profile-performance-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_resume_notification(
+        &mut self,
+        slept: Duration,
+        before: &BatterySnapshot,
+        after: &BatterySnapshot,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::Resume;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let body = match after.percentage.cmp(&before.percentage) {
+            std::cmp::Ordering::Less => format!(
+                "Battery drained {}% during sleep ({}% → {}%)",
+                before.percentage - after.percentage,
+                before.percentage,
+                after.percentage
+            ),
+            std::cmp::Ordering::Greater => format!(
+                "Battery charged {}% during sleep ({}% → {}%)",
+                after.percentage - before.percentage,
+                before.percentage,
+                after.percentage
+            ),
+            std::cmp::Ordering::Equal => {
+                format!("No battery drain during sleep ({}%)", after.percentage)
+            }
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(&format!(
+                "Resumed after {}",
+                format_duration(slept.as_secs() as i64)
+            ))
+            .with_body(&body)
+            .with_icon("system-suspend-symbolic")
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_shutdown_notification(&mut self) -> anyhow::Result<()> {
+        let key = NotificationKey::Shutdown;
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary("Shutting down")
+            .with_icon("system-shutdown-symbolic")
+            .with_urgency(notify::Urgency::Normal)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_lid_notification(&mut self, closed: bool) -> anyhow::Result<()> {
+        let key = NotificationKey::Lid(closed);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let summary = if closed { "Lid closed" } else { "Lid opened" };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(summary)
+            .with_icon("computer-symbolic")
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn close_notification(&mut self, key: NotificationKey) -> anyhow::Result<()> {
+        if let Some(id) = self.active_notifications.remove(&key) {
+            self.builder.close(id).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_battery_level_notification(
+        &mut self,
+        battery: &BatterySnapshot,
+        level: &BatteryLevel,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::BatteryLevel(*level);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, icon, urgency) = match level {
+            BatteryLevel::Critical => (
+                "Battery level critical",
+                "battery-caution-symbolic",
+                notify::Urgency::Critical,
+            ),
+            BatteryLevel::Low => (
+                "Battery level low",
+                "battery-low-symbolic",
+                notify::Urgency::Normal,
+            ),
+            BatteryLevel::Normal => (
+                "Battery level normal",
+                "battery-good-symbolic",
+                notify::Urgency::Low,
+            ),
+            BatteryLevel::High => (
+                "Battery level high",
+                "battery-full-symbolic",
+                notify::Urgency::Low,
+            ),
+            BatteryLevel::Full => (
+                "Battery level full",
+                "battery-full-charged-symbolic",
+                notify::Urgency::Low,
+            ),
+            _ => return Ok(()),
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(summary)
+            .with_icon(icon)
+            .with_urgency(urgency)
+            .with_body(&battery_body(battery))
+            .with_progress(battery.percentage as i32)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+
+    async fn send_power_source_notification(
+        &mut self,
+        battery: &BatterySnapshot,
+        on_battery: bool,
+    ) -> anyhow::Result<()> {
+        let key = NotificationKey::PowerSource(on_battery);
+        let id = *self.active_notifications.get(&key).unwrap_or(&0);
+
+        let (summary, icon) = if on_battery {
+            ("Running on battery power", "battery-symbolic")
+        } else {
+            ("Connected to power", "ac-adapter-symbolic")
+        };
+
+        let new_id = self
+            .builder
+            .clone()
+            .with_summary(summary)
+            .with_body(&battery_body(battery))
+            .with_progress(battery.percentage as i32)
+            .with_icon(icon)
+            .with_id(id)
+            .send()
+            .await?;
+
+        self.active_notifications.insert(key, new_id);
+        Ok(())
+    }
+}
+
+/// What a notification is about, later notifications about the same thing
+/// replace it instead of piling up.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+enum NotificationKey {
+    Volume,
+    Brightness,
+    Microphone,
+    LockKey(LockKey),
+    /// MPRIS bus name of the player.
+    NowPlaying(String),
+    Recording(RecordingDevice),
+    /// Card index.
+    AudioProfile(u32),
+    /// Sink index.
+    AudioPort(u32),
+    BatteryState(BatteryState),
+    BatteryLevel(BatteryLevel),
+    /// Whether running on battery.
+    PowerSource(bool),
+    UnplugReminder,
+    PlugInReminder,
+    BatteryHealth,
+    Network,
+    Connectivity(Connectivity),
+    /// VPN connection name.
+    Vpn(String),
+    /// Device alias.
+    Bluetooth(String),
+    BluetoothBattery(String),
+    /// The filesystem, without its mount point, as actions need it.
+    Storage(StorageDevice),
+    /// Sysfs path of the physical device.
+    Hotplug(String),
+    /// Mount point.
+    DiskSpace(String),
+    /// Sensor name.
+    Overheating(String),
+    FanFailure(String),
+    Pressure(Resource),
+    /// Name of the process killed.
+    OomKill(String),
+    UnitFailed(UnitScope, String),
+    PowerProfile,
+    PerformanceDegraded,
+    Resume,
+    Shutdown,
+    Lid(bool),
+}
+
+impl NotificationKey {
+    /// Every change of one filesystem, mounted or not, shares a notification.
+    fn storage(device: &StorageDevice) -> Self {
+        Self::Storage(StorageDevice {
+            mount_point: None,
+            ..device.clone()
+        })
+    }
+}
+
+/// Sends a reminder once its condition is met (`Some(true)`) and closes it once
+/// the condition no longer holds (`Some(false)`), rather than on every change
+/// of the battery percentage.
+fn remind(reminded: &mut bool, due: bool) -> Option<bool> {
+    let change = (due != *reminded).then_some(due);
+    *reminded = due;
+    change
+}
+
+fn format_duration(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, minutes) => format!("{minutes}m"),
+        (hours, minutes) => format!("{hours}h {minutes}m"),
+    }
+}
+
+fn battery_body(battery: &BatterySnapshot) -> String {
+    let mut body = format!("Current battery percentage: {}%", battery.percentage);
+    match battery.state {
+        BatteryState::Charging if battery.time_to_full > 0 => {
+            body += &format!("\n{} until full", format_duration(battery.time_to_full));
+        }
+        BatteryState::Discharging if battery.time_to_empty > 0 => {
+            body += &format!("\n{} remaining", format_duration(battery.time_to_empty));
+        }
+        _ => {}
+    }
+    body
+}
+
+#[derive(PartialEq, Eq, Hash, Clone)]
+enum Event {
+    VolumeChanged,
+    SourceVolumeChanged,
+    AudioRoutesChanged,
+    BatteryState(BatteryState),
+    BatteryLevel(BatteryLevel),
+    OnBattery(bool),
+    BatteryPercentage(u64),
+    ActionInvoked(u32, String),
+    NotificationClosed(u32),
+    BatteryHealth,
+    PowerProfile(String),
+    PerformanceDegraded(String),
+    PrepareForSleep(bool),
+    PrepareForShutdown(bool),
+    LidClosed(bool),
+    CriticalCountdown,
+    PrimaryConnection(Option<NetworkConnection>),
+    Connectivity(Connectivity),
+    Vpn(String, bool),
+    BluetoothConnected(BluetoothDevice),
+    BluetoothBattery(BluetoothDevice),
+    Storage(StorageDevice, StorageChange),
+    /// A drive's UDisks2 object path, once it is unplugged.
+    DriveRemoved(String),
+    DiskSpace(DiskSpace),
+    Overheating(Sensor),
+    FanFailure(String),
+    Pressure(Pressure),
+    OomKill(String),
+    UnitFailed(FailedUnit),
+    LockKey(LockKey, bool),
+    NowPlaying(Track),
+    PlayerStopped(String),
+    Hotplug(HotplugDevice, HotplugChange),
+    SourceOutputsChanged,
+    Recording(RecordingDevice, Vec<String>),
+    Brightness(u32),
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    match std::env::args().nth(1).as_deref() {
+        Some("battery-health") => return HealthHistory::new()?.print_trend(),
+        Some("status") => return status::print_stream(),
+        Some(command) => anyhow::bail!("Unknown command: {command}"),
+        None => {}
+    }
+
+    let config = Config::load()?;
+    let sysnotifier = SysNotifier::new(config).await?;
+    sysnotifier.run().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audio::mock::MockBackend;
+    use audio::{CardProfile, SinkPort};
+    use hotplug::DeviceKind;
+    use pressure::Process;
+    use test_support::{FakeServer, Notification};
+    use upower::BatteryHealth;
+
+    async fn notifier() -> anyhow::Result<(FakeServer, Notifier<'static>)> {
+        let (server, client) = FakeServer::start().await?;
+        Ok((server, Notifier::with_connection(client)))
+    }
+
+    /// A new notification with the builder defaults, to be filled in by tests.
+    fn expected() -> Notification {
+        Notification {
+            id: 1,
+            app_name: "SysNotifier".into(),
+            urgency: Some(notify::Urgency::Low as u8),
+            timeout: -1,
+            ..Notification::default()
+        }
+    }
+
+    fn battery(percentage: u64, state: BatteryState) -> BatterySnapshot {
+        BatterySnapshot {
+            percentage,
+            state,
+            time_to_empty: 1800,
+            time_to_full: 3600,
+            ..BatterySnapshot::default()
+        }
+    }
+
+    fn actions(actions: &[&str]) -> Vec<String> {
+        actions.iter().map(|action| action.to_string()).collect()
+    }
+
+    /// Audio state tracked against a mock sound server, announced to the fake
+    /// notification server.
+    struct Harness {
+        backend: MockBackend,
+        events: mpsc::Receiver<Event>,
+        audio: AudioTracker,
+        notifier: Notifier<'static>,
+        server: FakeServer,
+    }
+
+    impl Harness {
+        async fn new(backend: MockBackend) -> anyhow::Result<Self> {
+            let (server, notifier) = notifier().await?;
+
+            let (tx, events) = mpsc::channel();
+            let mut boxed: Box<dyn AudioBackend> = Box::new(backend.clone());
+            boxed.subscribe(tx, true);
+
+            Ok(Self {
+                backend,
+                events,
+                audio: AudioTracker::new(boxed)?,
+                notifier,
+                server,
+            })
+        }
+
+        /// Handles the events the mock sent so far, like `SysNotifier::run`.
+        async fn pump(&mut self) -> anyhow::Result<()> {
+            while let Ok(event) = self.events.try_recv() {
+                match event {
+                    Event::VolumeChanged => self.audio.volume_changed(&mut self.notifier).await?,
+                    Event::SourceVolumeChanged => {
+                        self.audio.source_volume_changed(&mut self.notifier).await?
+                    }
+                    Event::AudioRoutesChanged => {
+                        self.audio.routes_changed(&mut self.notifier).await?
+                    }
+                    Event::SourceOutputsChanged => {
+                        self.audio.recordings_changed(&mut self.notifier).await?
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+
+        /// Replaced ID, icon, summary and progress of the notifications
+        /// received since the last call.
+        async fn received(&self) -> anyhow::Result<Vec<(u32, String, String, Option<i32>)>> {
+            Ok(self
+                .server
+                .received()
+                .await?
+                .into_iter()
+                .map(|notification| {
+                    (
+                        notification.replaces_id,
+                        notification.icon,
+                        notification.summary,
+                        notification.progress,
+                    )
+                })
+                .collect())
+        }
+    }
+
+    fn volume(replaces_id: u32, icon: &str, value: i32) -> (u32, String, String, Option<i32>) {
+        (
+            replaces_id,
+            icon.into(),
+            format!("Volume [ {value}% ]"),
+            Some(value),
+        )
+    }
+
+    fn port(name: &str, description: &str) -> SinkPort {
+        SinkPort {
+            sink: "Built-in Audio".into(),
+            name: name.into(),
+            description: description.into(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn volume_icon_follows_thresholds() -> anyhow::Result<()> {
+        let mut harness = Harness::new(MockBackend::default()).await?;
+
+        for value in [32, 33, 65, 66, 100, 0] {
+            harness.backend.set_sink_volume(value, false);
+            harness.pump().await?;
+        }
+
+        assert_eq!(
+            harness.received().await?,
+            vec![
+                volume(0, "audio-volume-low-symbolic", 32),
+                volume(1, "audio-volume-medium-symbolic", 33),
+                volume(1, "audio-volume-medium-symbolic", 65),
+                volume(1, "audio-volume-high-symbolic", 66),
+                volume(1, "audio-volume-high-symbolic", 100),
+                volume(1, "audio-volume-muted-symbolic", 0),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mute_replaces_volume_without_progress() -> anyhow::Result<()> {
+        let mut harness = Harness::new(MockBackend::default()).await?;
+
+        harness.backend.set_sink_volume(50, false);
+        harness.pump().await?;
+        harness.backend.set_sink_volume(50, true);
+        harness.pump().await?;
+
+        assert_eq!(
+            harness.received().await?,
+            vec![
+                volume(0, "audio-volume-medium-symbolic", 50),
+                (
+                    1,
+                    "audio-volume-muted-symbolic".into(),
+                    "Volume Muted".into(),
+                    None
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unchanged_volume_is_not_repeated() -> anyhow::Result<()> {
+        let mut harness = Harness::new(MockBackend::default()).await?;
+
+        harness.backend.set_sink_volume(40, false);
+        harness.pump().await?;
+        harness.backend.set_sink_volume(40, false);
+        harness.pump().await?;
+        assert_eq!(harness.received().await?.len(), 1);
+
+        // Waking up must not announce the volume nobody touched.
+        harness.audio.resync();
+        harness.backend.set_sink_volume(40, false);
+        harness.pump().await?;
+        assert!(harness.received().await?.is_empty());
+
+        harness.backend.set_sink_volume(45, false);
+        harness.pump().await?;
+        assert_eq!(
+            harness.received().await?,
+            vec![volume(1, "audio-volume-medium-symbolic", 45)]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sink_state_is_cached_for_the_status() -> anyhow::Result<()> {
+        let mut harness = Harness::new(MockBackend::default()).await?;
+        assert_eq!(harness.audio.sink_volume, None);
+
+        harness.backend.set_sink_description("Headphones");
+        harness.backend.set_sink_volume(40, true);
+        harness.pump().await?;
+
+        assert_eq!(
+            harness.audio.sink_volume,
+            Some(Volume {
+                value: 40,
+                muted: true
+            })
+        );
+        assert_eq!(
+            harness.audio.sink_description.as_deref(),
+            Some("Headphones")
+        );
+
+        // What `publish_status` sends once the event is handled.
+        let status = Status::new(
+            harness.audio.sink_volume.as_ref(),
+            harness.audio.sink_description.as_deref(),
+            &BatterySnapshot::default(),
+        );
+        assert_eq!(status.volume, Some(40));
+        assert!(status.muted);
+        assert_eq!(status.sink.as_deref(), Some("Headphones"));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn only_microphone_mute_toggles_are_announced() -> anyhow::Result<()> {
+        let backend = MockBackend::default();
+        backend.set_source_volume(80, false);
+        let mut harness = Harness::new(backend).await?;
+
+        for muted in [false, true, false] {
+            harness.backend.set_source_volume(60, muted);
+            harness.pump().await?;
+        }
+
+        assert_eq!(
+            harness.received().await?,
+            vec![
+                (
+                    0,
+                    "microphone-sensitivity-muted-symbolic".into(),
+                    "Microphone Muted".into(),
+                    None
+                ),
+                (
+                    1,
+                    "audio-input-microphone-symbolic".into(),
+                    "Microphone On".into(),
+                    None
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn device_changes_are_announced() -> anyhow::Result<()> {
+        let backend = MockBackend::default();
+        backend.set_port(0, port("analog-output-speaker", "Speakers"));
+        backend.set_profile(
+            1,
+            CardProfile {
+                card: "WH-1000XM4".into(),
+                name: "a2dp-sink".into(),
+                description: "High Fidelity Playback (A2DP Sink)".into(),
+            },
+        );
+        let mut harness = Harness::new(backend).await?;
+        harness.pump().await?;
+        assert!(harness.received().await?.is_empty());
+
+        harness
+            .backend
+            .set_port(0, port("analog-output-headphones", "Headphones"));
+        harness.pump().await?;
+        harness
+            .backend
+            .set_port(0, port("analog-output-speaker", "Speakers"));
+        harness.pump().await?;
+        harness.backend.set_profile(
+            1,
+            CardProfile {
+                card: "WH-1000XM4".into(),
+                name: "headset-head-unit".into(),
+                description: "Headset Head Unit (HSP/HFP)".into(),
+            },
+        );
+        harness.pump().await?;
+
+        let received: Vec<_> = harness
+            .server
+            .received()
+            .await?
+            .into_iter()
+            .map(|notification| {
+                (
+                    notification.replaces_id,
+                    notification.icon,
+                    notification.summary,
+                    notification.body,
+                )
+            })
+            .collect();
+        // The card's notification doesn't replace the sink's.
+        assert_eq!(
+            received,
+            vec![
+                (
+                    0,
+                    "audio-headphones-symbolic".into(),
+                    "Headphones plugged in".into(),
+                    "Built-in Audio".into(),
+                ),
+                (
+                    1,
+                    "audio-speakers-symbolic".into(),
+                    "Headphones unplugged".into(),
+                    "Switched to Speakers".into(),
+                ),
+                (
+                    0,
+                    "audio-headset-symbolic".into(),
+                    "Switched to Headset Head Unit (HSP/HFP) — reduced quality".into(),
+                    "WH-1000XM4".into(),
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn microphone_users_are_announced_once() -> anyhow::Result<()> {
+        let mut harness = Harness::new(MockBackend::default()).await?;
+
+        for users in [&["Firefox"][..], &["Firefox"], &["Firefox", "OBS"], &[]] {
+            harness.backend.set_recordings(users);
+            harness.pump().await?;
+        }
+
+        let received = harness.server.received().await?;
+        assert_eq!(
+            received,
+            vec![
+                Notification {
+                    icon: "audio-input-microphone-symbolic".into(),
+                    summary: "Microphone in use by Firefox".into(),
+                    urgency: Some(notify::Urgency::Normal as u8),
+                    timeout: 0,
+                    ..expected()
+                },
+                Notification {
+                    replaces_id: 1,
+                    icon: "audio-input-microphone-symbolic".into(),
+                    summary: "Microphone in use by Firefox, OBS".into(),
+                    urgency: Some(notify::Urgency::Normal as u8),
+                    timeout: 0,
+                    ..expected()
+                },
+            ]
+        );
+        // The last change closes the notification instead.
+        assert_eq!(harness.server.closed().await?, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn actions_are_forwarded_as_events() -> anyhow::Result<()> {
+        let (server, notifier) = notifier().await?;
+        let (tx, rx) = mpsc::channel();
+        notifier.subscribe(tx).await?;
+
+        server.invoke_action(7, "storage-open").await?;
+
+        let event = rx.recv_timeout(Duration::from_secs(5))?;
+        assert!(event == Event::ActionInvoked(7, "storage-open".into()));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn closed_notifications_are_forgotten() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let (tx, rx) = mpsc::channel();
+        notifier.subscribe(tx).await?;
+
+        notifier.send_lid_notification(true).await?;
+        server.dismiss(1).await?;
+
+        let event = rx.recv_timeout(Duration::from_secs(5))?;
+        assert!(event == Event::NotificationClosed(1));
+        notifier.forget(1);
+        assert!(notifier.active_notifications.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dismissed_notifications_are_reopened() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier.send_lid_notification(true).await?;
+        server.dismiss(1).await?;
+        notifier.send_lid_notification(true).await?;
+        notifier.send_lid_notification(true).await?;
+
+        let ids: Vec<_> = server
+            .received()
+            .await?
+            .into_iter()
+            .map(|notification| (notification.replaces_id, notification.id))
+            .collect();
+        assert_eq!(ids, vec![(0, 1), (1, 2), (2, 2)]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn lock_key() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_lock_key_notification(LockKey::Caps, true, 1500)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "input-keyboard-symbolic".into(),
+                summary: "Caps Lock on".into(),
+                timeout: 1500,
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_lock_key_notification(LockKey::Caps, false, 1500)
+            .await?;
+        let notification = server.single().await?;
+        assert_eq!(notification.replaces_id, 1);
+        assert_eq!(notification.summary, "Caps Lock off");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn now_playing() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let track = Track {
+            player: "org.mpris.MediaPlayer2.spotify".into(),
+            identity: "Spotify".into(),
+            title: "Song".into(),
+            artist: "Artist".into(),
+            album: "Album".into(),
+            art: Some("file:///tmp/cover.png".into()),
+            playing: true,
+        };
+
+        notifier.send_now_playing_notification(&track).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "audio-x-generic-symbolic".into(),
+                summary: "Song".into(),
+                body: "Artist — Album".into(),
+                actions: actions(&["media-play-pause", "Pause", "media-next", "Next"]),
+                image: Some("file:///tmp/cover.png".into()),
+                ..expected()
+            }
+        );
+
+        let paused = Track {
+            artist: String::new(),
+            album: String::new(),
+            art: None,
+            playing: false,
+            ..track
+        };
+        notifier.send_now_playing_notification(&paused).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "audio-x-generic-symbolic".into(),
+                summary: "Song (paused)".into(),
+                body: "Spotify".into(),
+                actions: actions(&["media-play-pause", "Play", "media-next", "Next"]),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn camera_recording() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_recording_notification(RecordingDevice::Camera, &["zoom".into()])
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "camera-web-symbolic".into(),
+                summary: "Camera in use by zoom".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                timeout: 0,
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn battery_state() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_battery_state_notification(
+                &battery(50, BatteryState::Charging),
+                &BatteryState::Charging,
+            )
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-charging-symbolic".into(),
+                summary: "Battery is charging".into(),
+                body: "Current battery percentage: 50%\n1h 0m until full".into(),
+                progress: Some(50),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_battery_state_notification(
+                &battery(50, BatteryState::Discharging),
+                &BatteryState::Discharging,
+            )
+            .await?;
+        assert!(server.received().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn charging_progress() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_charging_progress_notification(&battery(62, BatteryState::Charging))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-charging-symbolic".into(),
+                summary: "Charging".into(),
+                body: "Current battery percentage: 62%\n1h 0m until full".into(),
+                progress: Some(62),
+                timeout: 0,
+                ..expected()
+            }
+        );
+
+        notifier.close_charging_progress_notification().await?;
+        assert_eq!(server.closed().await?, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unplug_reminder() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let battery = battery(80, BatteryState::Charging);
+
+        let threshold = ChargeThreshold {
+            enabled: true,
+            start: 75,
+            end: 80,
+        };
+        notifier
+            .send_unplug_reminder(&battery, Some(threshold))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-full-charging-symbolic".into(),
+                summary: "Charge limit reached".into(),
+                body: "Battery at 80%. Hardware charge limit (75–80%) is on.".into(),
+                actions: actions(&["toggle-charge-threshold", "Disable charge limit"]),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(80),
+                ..expected()
+            }
+        );
+
+        notifier.send_unplug_reminder(&battery, None).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "battery-full-charging-symbolic".into(),
+                summary: "Unplug the charger".into(),
+                body: "Battery at 80%, unplug the charger to preserve battery health".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(80),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn reminders_are_sent_once_per_crossing() {
+        let mut reminded = false;
+        let changes: Vec<_> = [false, true, true, true, false, false, true]
+            .into_iter()
+            .map(|due| remind(&mut reminded, due))
+            .collect();
+
+        assert_eq!(
+            changes,
+            [None, Some(true), None, None, Some(false), None, Some(true)]
+        );
+        assert!(reminded);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn plug_in_reminder() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_plug_in_reminder(&battery(20, BatteryState::Discharging))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-low-symbolic".into(),
+                summary: "Plug in the charger".into(),
+                body: "Battery at 20%, plug in the charger to preserve battery health".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(20),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn battery_health() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let health = BatteryHealth {
+            capacity: 78.46,
+            energy_full: 44.7,
+            energy_full_design: 57.0,
+            charge_cycles: 312,
+            technology: "lithium-ion",
+        };
+
+        notifier
+            .send_battery_health_notification(&health, 80.0)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-caution-symbolic".into(),
+                summary: "Battery health below 80%".into(),
+                body: "Capacity is 78.5% of design (44.7 of 57.0 Wh), 312 charge cycles".into(),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn critical_countdown() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_critical_countdown_notification(
+                &battery(4, BatteryState::Discharging),
+                CriticalAction::Suspend,
+                Duration::from_secs(60),
+            )
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-caution-symbolic".into(),
+                summary: "Suspending in 60s".into(),
+                body: "Battery critical at 4%, connect the charger".into(),
+                actions: actions(&[
+                    "critical-now",
+                    "Suspend now",
+                    "critical-postpone",
+                    "Postpone"
+                ]),
+                urgency: Some(notify::Urgency::Critical as u8),
+                progress: Some(4),
+                timeout: 0,
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn network() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let wifi = NetworkConnection {
+            id: "Home".into(),
+            kind: "802-11-wireless".into(),
+            ssid: Some("HomeNet".into()),
+            strength: Some(72),
+        };
+
+        notifier.send_network_notification(Some(&wifi)).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "network-wireless-signal-good-symbolic".into(),
+                summary: "Connected to HomeNet".into(),
+                body: "Signal strength 72%".into(),
+                ..expected()
+            }
+        );
+
+        notifier.send_network_notification(None).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "network-offline-symbolic".into(),
+                summary: "Disconnected".into(),
+                body: "No network connection".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn connectivity() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_connectivity_notification(Connectivity::Portal)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "network-error-symbolic".into(),
+                summary: "Sign in to network".into(),
+                body: "A captive portal is blocking internet access".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_connectivity_notification(Connectivity::Full)
+            .await?;
+        assert!(server.received().await?.is_empty());
+        assert_eq!(server.closed().await?, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn vpn() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier.send_vpn_notification("work", true).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "network-vpn-symbolic".into(),
+                summary: "VPN work connected".into(),
+                ..expected()
+            }
+        );
+
+        notifier.send_vpn_notification("work", false).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "network-vpn-disconnected-symbolic".into(),
+                summary: "VPN work disconnected".into(),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bluetooth() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let headphones = BluetoothDevice {
+            alias: "Headphones".into(),
+            icon: "audio-headset".into(),
+            connected: true,
+            battery: Some(80),
+        };
+
+        notifier.send_bluetooth_notification(&headphones).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "audio-headset".into(),
+                summary: "Headphones connected — 80%".into(),
+                progress: Some(80),
+                ..expected()
+            }
+        );
+
+        let disconnected = BluetoothDevice {
+            connected: false,
+            ..headphones.clone()
+        };
+        notifier.send_bluetooth_notification(&disconnected).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "audio-headset".into(),
+                summary: "Headphones disconnected".into(),
+                ..expected()
+            }
+        );
+
+        let low = BluetoothDevice {
+            battery: Some(15),
+            ..headphones
+        };
+        notifier.send_bluetooth_battery_notification(&low).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 2,
+                icon: "battery-low-symbolic".into(),
+                summary: "Headphones battery low".into(),
+                body: "Battery at 15%".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(15),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn storage() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let stick = StorageDevice {
+            path: "/org/freedesktop/UDisks2/block_devices/sdb1".into(),
+            drive: "/org/freedesktop/UDisks2/drives/SanDisk".into(),
+            label: "USB".into(),
+            device: "/dev/sdb1".into(),
+            size: 16_000_000_000,
+            mount_point: None,
+        };
+
+        notifier
+            .send_storage_notification(&stick, StorageChange::Inserted)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "drive-removable-media-symbolic".into(),
+                summary: "USB inserted".into(),
+                body: "/dev/sdb1 (16.0 GB)".into(),
+                actions: actions(&["storage-open", "Open", "storage-power-off", "Power off"]),
+                ..expected()
+            }
+        );
+
+        let mounted = StorageDevice {
+            mount_point: Some("/run/media/user/USB".into()),
+            ..stick.clone()
+        };
+        notifier
+            .send_storage_notification(&mounted, StorageChange::Mounted)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "drive-removable-media-symbolic".into(),
+                summary: "USB mounted".into(),
+                body: "Mounted at /run/media/user/USB".into(),
+                actions: actions(&[
+                    "storage-open",
+                    "Open",
+                    "storage-unmount",
+                    "Unmount",
+                    "storage-power-off",
+                    "Power off",
+                ]),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_storage_error_notification(&mounted, "unmount", &anyhow::anyhow!("Device busy"))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "dialog-error-symbolic".into(),
+                summary: "Failed to unmount USB".into(),
+                body: "Device busy".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_storage_notification(&stick, StorageChange::SafeToRemove)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "media-eject-symbolic".into(),
+                summary: "USB can be safely removed".into(),
+                body: "/dev/sdb1".into(),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_storage_notification(&stick, StorageChange::Removed)
+            .await?;
+        assert_eq!(server.single().await?.summary, "USB removed");
+        assert!(notifier.active_notifications.is_empty());
+
+        // Powered off drives don't announce their filesystems being removed.
+        notifier
+            .send_storage_notification(&stick, StorageChange::Inserted)
+            .await?;
+        assert_eq!(server.single().await?.replaces_id, 0);
+        notifier.forget_drive(&stick.drive);
+        assert!(notifier.active_notifications.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn hotplug() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let keyboard = HotplugDevice {
+            root: "/sys/devices/pci0000:00/0000:00:14.0/usb1/1-2".into(),
+            kind: DeviceKind::Keyboard,
+            name: "Keychron K2".into(),
+            ids: Some((0x05ac, 0x024f)),
+        };
+
+        notifier
+            .send_hotplug_notification(&keyboard, HotplugChange::Added)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "input-keyboard-symbolic".into(),
+                summary: "Keyboard connected".into(),
+                body: "Keychron K2".into(),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_hotplug_notification(&keyboard, HotplugChange::Removed)
+            .await?;
+        let notification = server.single().await?;
+        assert_eq!(notification.replaces_id, 1);
+        assert_eq!(notification.summary, "Keyboard disconnected");
+        assert!(notifier.active_notifications.is_empty());
+
+        notifier
+            .send_hotplug_notification(&keyboard, HotplugChange::Added)
+            .await?;
+        assert_eq!(server.single().await?.replaces_id, 0);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disk_space() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let space = |level, free| DiskSpace {
+            path: "/".into(),
+            level,
+            free,
+            total: 100_000_000_000,
+        };
+
+        notifier
+            .send_disk_space_notification(&space(DiskLevel::Low, 9_000_000_000))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "drive-harddisk-symbolic".into(),
+                summary: "Low disk space on /".into(),
+                body: "9.0 GB free of 100.0 GB (9.0%)".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(91),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_disk_space_notification(&space(DiskLevel::Critical, 4_000_000_000))
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "drive-harddisk-symbolic".into(),
+                summary: "Disk almost full on /".into(),
+                body: "4.0 GB free of 100.0 GB (4.0%)".into(),
+                urgency: Some(notify::Urgency::Critical as u8),
+                progress: Some(96),
+                timeout: 0,
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_disk_space_notification(&space(DiskLevel::Normal, 50_000_000_000))
+            .await?;
+        assert!(server.received().await?.is_empty());
+        assert_eq!(server.closed().await?, vec![1]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn thermal() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let sensor = Sensor {
+            name: "coretemp Package id 0".into(),
+            temperature: 97000,
+            crit: Some(100000),
+        };
+
+        notifier.send_overheating_notification(&sensor).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "sensors-temperature-symbolic".into(),
+                summary: "coretemp Package id 0 is running hot".into(),
+                body: "97.0 °C, critical at 100.0 °C".into(),
+                urgency: Some(notify::Urgency::Critical as u8),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_fan_failure_notification("thinkpad fan1")
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 2,
+                icon: "sensors-fan-symbolic".into(),
+                summary: "Fan failure".into(),
+                body: "thinkpad fan1 reports a fault".into(),
+                urgency: Some(notify::Urgency::Critical as u8),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pressure() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let pressure = Pressure {
+            resource: Resource::Memory,
+            avg10: 35,
+            top: vec![Process {
+                pid: 4321,
+                name: "firefox".into(),
+                rss: 2_000_000,
+            }],
+        };
+
+        notifier.send_pressure_notification(&pressure).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "dialog-warning-symbolic".into(),
+                summary: "High memory pressure".into(),
+                body: "Tasks stalled 35% of the last 10 seconds\nTop consumers: firefox (2.0 GB)"
+                    .into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+
+        notifier.send_oom_kill_notification("firefox").await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 2,
+                icon: "dialog-error-symbolic".into(),
+                summary: "Out of memory".into(),
+                body: "firefox was killed to free memory".into(),
+                urgency: Some(notify::Urgency::Critical as u8),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unit_failed() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let unit = FailedUnit {
+            scope: UnitScope::System,
+            name: "backup.service".into(),
+            result: "exit-code".into(),
+            exit_status: Some(1),
+        };
+
+        notifier.send_unit_failed_notification(&unit).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "dialog-error-symbolic".into(),
+                summary: "System unit backup.service failed".into(),
+                body: "Result: exit-code, exit status 1".into(),
+                actions: actions(&["unit-logs", "Show logs", "unit-restart", "Restart"]),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_unit_restart_error_notification(
+                unit.scope,
+                &unit.name,
+                &anyhow::anyhow!("Access denied"),
+            )
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                replaces_id: 1,
+                icon: "dialog-error-symbolic".into(),
+                summary: "Failed to restart backup.service".into(),
+                body: "Access denied".into(),
+                actions: actions(&["unit-logs", "Show logs"]),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn power_profiles() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_power_profile_notification("power-saver")
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "power-profile-power-saver-symbolic".into(),
+                summary: "Power profile: Power Saver".into(),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_performance_degraded_notification("lap-detected")
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 2,
+                icon: "power-profile-performance-symbolic".into(),
+                summary: "Performance mode degraded".into(),
+                body: "The computer is sitting on a lap".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+
+        // Profile changes replace each other.
+        notifier
+            .send_power_profile_notification("performance")
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 1,
+                replaces_id: 1,
+                icon: "power-profile-performance-symbolic".into(),
+                summary: "Power profile: Performance".into(),
+                ..expected()
+            }
+        );
+
+        notifier
+            .send_performance_degraded_notification("high-operating-temperature")
+            .await?;
+        assert_eq!(server.single().await?.replaces_id, 2);
+
+        notifier.send_performance_degraded_notification("").await?;
+        assert!(server.received().await?.is_empty());
+        assert_eq!(server.closed().await?, vec![2]);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn logind() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_resume_notification(
+                Duration::from_secs(7500),
+                &battery(80, BatteryState::Discharging),
+                &battery(74, BatteryState::Discharging),
+            )
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "system-suspend-symbolic".into(),
+                summary: "Resumed after 2h 5m".into(),
+                body: "Battery drained 6% during sleep (80% → 74%)".into(),
+                ..expected()
+            }
+        );
+
+        notifier.send_lid_notification(true).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 2,
+                icon: "computer-symbolic".into(),
+                summary: "Lid closed".into(),
+                ..expected()
+            }
+        );
+
+        notifier.send_shutdown_notification().await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                id: 3,
+                icon: "system-shutdown-symbolic".into(),
+                summary: "Shutting down".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn brightness() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier.send_brightness_notification(60).await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "display-brightness-symbolic".into(),
+                summary: "Brightness [ 60% ]".into(),
+                progress: Some(60),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn volume_is_routed_to_the_osd() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+        let fifo =
+            std::env::temp_dir().join(format!("sysnotifier-osd-routing-{}", std::process::id()));
+        std::fs::write(&fifo, "")?;
+        notifier.osd = Osd::new(config::OsdConfig {
+            show: vec![OsdSource::Volume],
+            path: Some(fifo.clone()),
+            ..config::OsdConfig::default()
+        })?;
+
+        let volume = Volume {
+            value: 55,
+            muted: false,
+        };
+        notifier.send_volume_notification(&volume).await?;
+        notifier.send_brightness_notification(30).await?;
+        assert_eq!(std::fs::read_to_string(&fifo)?, "55\n");
+        assert_eq!(server.single().await?.summary, "Brightness [ 30% ]");
+
+        // Nothing reads the OSD, the volume must not get lost.
+        std::fs::remove_file(&fifo)?;
+        notifier.send_volume_notification(&volume).await?;
+        assert_eq!(server.single().await?.summary, "Volume [ 55% ]");
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn battery_level() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_battery_level_notification(
+                &battery(15, BatteryState::Discharging),
+                &BatteryLevel::Low,
+            )
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-low-symbolic".into(),
+                summary: "Battery level low".into(),
+                body: "Current battery percentage: 15%\n30m remaining".into(),
+                urgency: Some(notify::Urgency::Normal as u8),
+                progress: Some(15),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn power_source() -> anyhow::Result<()> {
+        let (server, mut notifier) = notifier().await?;
+
+        notifier
+            .send_power_source_notification(&battery(90, BatteryState::Discharging), true)
+            .await?;
+        assert_eq!(
+            server.single().await?,
+            Notification {
+                icon: "battery-symbolic".into(),
+                summary: "Running on battery power".into(),
+                body: "Current battery percentage: 90%\n30m remaining".into(),
+                progress: Some(90),
+                ..expected()
+            }
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,235 @@
+use crate::Event;
+use crate::config::MprisConfig;
+use futures_lite::StreamExt;
+use std::{collections::HashMap, sync::mpsc};
+use zbus::{
+    fdo::DBusProxy,
+    names::OwnedBusName,
+    proxy,
+    zvariant::{OwnedValue, Value},
+};
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+/// What a player is playing, `player` is its bus name.
+#[derive(PartialEq, Eq, Hash, Clone, Default, Debug)]
+pub struct Track {
+    pub player: String,
+    /// Human readable player name, e.g. "Spotify".
+    pub identity: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Local album art as a `file://` URI.
+    pub art: Option<String>,
+    pub playing: bool,
+}
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait MediaPlayer2 {
+    #[zbus(property)]
+    fn identity(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "org.mpris.MediaPlayer2.Player",
+    default_path = "/org/mpris/MediaPlayer2"
+)]
+trait Player {
+    #[zbus(property)]
+    fn playback_status(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn metadata(&self) -> zbus::Result<HashMap<String, OwnedValue>>;
+
+    fn play_pause(&self) -> zbus::Result<()>;
+
+    fn next(&self) -> zbus::Result<()>;
+}
+
+/// A metadata entry as text, lists such as `xesam:artist` are joined.
+fn text(metadata: &HashMap<String, OwnedValue>, key: &str) -> String {
+    match metadata.get(key).map(|value| &**value) {
+        Some(Value::Str(text)) => text.to_string(),
+        Some(Value::Array(list)) => list
+            .iter()
+            .filter_map(|value| match value {
+                Value::Str(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => String::new(),
+    }
+}
+
+async fn player_proxy(
+    connection: &zbus::Connection,
+    player: &str,
+) -> zbus::Result<PlayerProxy<'static>> {
+    PlayerProxy::builder(connection)
+        .destination(player.to_owned())?
+        .build()
+        .await
+}
+
+/// The current track, `None` once playback has stopped.
+async fn describe(connection: &zbus::Connection, player: &PlayerProxy<'_>) -> Option<Track> {
+    let status = player.playback_status().await.ok()?;
+    if status == "Stopped" {
+        return None;
+    }
+
+    let metadata = player.metadata().await.ok()?;
+    let title = text(&metadata, "xesam:title");
+    if title.is_empty() {
+        return None;
+    }
+
+    let name = player.inner().destination().to_string();
+    let identity = match MediaPlayer2Proxy::builder(connection)
+        .destination(name.clone())
+        .ok()?
+        .build()
+        .await
+    {
+        Ok(root) => root.identity().await.unwrap_or_default(),
+        Err(_) => String::new(),
+    };
+
+    let art = Some(text(&metadata, "mpris:artUrl")).filter(|url| url.starts_with("file://"));
+
+    Some(Track {
+        player: name,
+        identity,
+        title,
+        artist: text(&metadata, "xesam:artist"),
+        album: text(&metadata, "xesam:album"),
+        art,
+        playing: status == "Playing",
+    })
+}
+
+/// Whether `name` is an MPRIS player that isn't ignored, e.g. `firefox` ignores
+/// `org.mpris.MediaPlayer2.firefox.instance_1_42`.
+fn wanted(ignore: &[String], name: &str) -> bool {
+    name.strip_prefix(MPRIS_PREFIX).is_some_and(|player| {
+        !ignore
+            .iter()
+            .any(|ignored| player.starts_with(ignored.as_str()))
+    })
+}
+
+fn handle_now_playing(event_sender: &mpsc::Sender<Event>, track: Track) {
+    _ = event_sender.send(Event::NowPlaying(track));
+}
+
+fn handle_player_stopped(event_sender: &mpsc::Sender<Event>, player: String) {
+    _ = event_sender.send(Event::PlayerStopped(player));
+}
+
+/// Follows one player, sending its track whenever it or the playback status changes.
+fn watch(
+    connection: &zbus::Connection,
+    name: String,
+    event_sender: &mpsc::Sender<Event>,
+) -> tokio::task::JoinHandle<()> {
+    let connection = connection.clone();
+    let event_sender = event_sender.clone();
+
+    tokio::spawn(async move {
+        let Ok(player) = player_proxy(&connection, &name).await else {
+            return;
+        };
+        let mut status_stream = player.receive_playback_status_changed().await;
+        let mut metadata_stream = player.receive_metadata_changed().await;
+
+        // Players tend to emit the same metadata repeatedly.
+        let mut current = describe(&connection, &player).await;
+        loop {
+            tokio::select! {
+                Some(_) = status_stream.next() => {}
+                Some(_) = metadata_stream.next() => {}
+                else => break,
+            }
+
+            let track = describe(&connection, &player).await;
+            if track == current {
+                continue;
+            }
+            match &track {
+                Some(track) => handle_now_playing(&event_sender, track.clone()),
+                None => handle_player_stopped(&event_sender, name.clone()),
+            }
+            current = track;
+        }
+    })
+}
+
+pub struct MprisManager {
+    connection: zbus::Connection,
+    config: MprisConfig,
+}
+
+impl MprisManager {
+    pub async fn new(config: MprisConfig) -> anyhow::Result<Self> {
+        let connection = zbus::Connection::session().await?;
+
+        Ok(Self { connection, config })
+    }
+
+    pub async fn play_pause(&self, player: &str) -> anyhow::Result<()> {
+        player_proxy(&self.connection, player)
+            .await?
+            .play_pause()
+            .await?;
+        Ok(())
+    }
+
+    pub async fn next(&self, player: &str) -> anyhow::Result<()> {
+        player_proxy(&self.connection, player).await?.next().await?;
+        Ok(())
+    }
+
+    /// Discovers players as they appear on the session bus.
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let dbus = DBusProxy::new(&self.connection).await?;
+        let mut owner_stream = dbus.receive_name_owner_changed().await?;
+
+        let mut watchers = HashMap::new();
+        for name in dbus.list_names().await? {
+            if wanted(&self.config.ignore, &name) {
+                let watcher = watch(&self.connection, name.to_string(), &event_sender);
+                watchers.insert(name, watcher);
+            }
+        }
+
+        let connection = self.connection.clone();
+        let ignore = self.config.ignore.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = owner_stream.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+                if !wanted(&ignore, &args.name) {
+                    continue;
+                }
+
+                let name = OwnedBusName::from(args.name.clone());
+                if let Some(watcher) = watchers.remove(&name) {
+                    watcher.abort();
+                    handle_player_stopped(&event_sender, name.to_string());
+                }
+                if args.new_owner.is_some() {
+                    let watcher = watch(&connection, name.to_string(), &event_sender);
+                    watchers.insert(name, watcher);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::{collections::HashMap, sync::mpsc};
+use zbus::{
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
+
+#[derive(
+    PartialEq, Eq, OwnedValue, Deserialize_repr, Serialize_repr, Default, Hash, Clone, Copy, Debug,
+)]
+#[repr(u32)]
+pub enum Connectivity {
+    #[default]
+    Unknown = 0,
+    None = 1,
+    Portal = 2,
+    Limited = 3,
+    Full = 4,
+}
+
+/// The connection carrying the default route, as announced in notifications.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct NetworkConnection {
+    pub id: String,
+    pub kind: String,
+    pub ssid: Option<String>,
+    /// Wi-Fi signal strength in percent.
+    pub strength: Option<u8>,
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager",
+    default_service = "org.freedesktop.NetworkManager",
+    default_path = "/org/freedesktop/NetworkManager"
+)]
+trait NetworkManager {
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn connectivity(&self) -> zbus::Result<Connectivity>;
+
+    #[zbus(property)]
+    fn primary_connection(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn active_connections(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager.Connection.Active",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait ActiveConnection {
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    #[zbus(property, name = "Type")]
+    fn type_(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn vpn(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn specific_object(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.NetworkManager.AccessPoint",
+    default_service = "org.freedesktop.NetworkManager"
+)]
+trait AccessPoint {
+    #[zbus(property)]
+    fn ssid(&self) -> zbus::Result<Vec<u8>>;
+
+    #[zbus(property)]
+    fn strength(&self) -> zbus::Result<u8>;
+}
+
+async fn active_connection(
+    connection: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> zbus::Result<ActiveConnectionProxy<'static>> {
+    ActiveConnectionProxy::builder(connection)
+        .path(path)?
+        .build()
+        .await
+}
+
+async fn describe(
+    connection: &zbus::Connection,
+    path: OwnedObjectPath,
+) -> Option<NetworkConnection> {
+    if path.as_str() == "/" {
+        return None;
+    }
+
+    let active = active_connection(connection, path).await.ok()?;
+    let mut network = NetworkConnection {
+        id: active.id().await.ok()?,
+        kind: active.type_().await.ok()?,
+        ssid: None,
+        strength: None,
+    };
+
+    if network.kind == "802-11-wireless" {
+        let access_point = match active.specific_object().await {
+            Ok(path) if path.as_str() != "/" => AccessPointProxy::builder(connection)
+                .path(path)
+                .ok()?
+                .build()
+                .await
+                .ok(),
+            _ => None,
+        };
+
+        if let Some(access_point) = access_point {
+            network.ssid = access_point
+                .ssid()
+                .await
+                .ok()
+                .map(|ssid| String::from_utf8_lossy(&ssid).into_owned());
+            network.strength = access_point.strength().await.ok();
+        }
+    }
+
+    Some(network)
+}
+
+/// VPN name of an active connection, `None` for anything that isn't a VPN.
+async fn vpn_name(connection: &zbus::Connection, path: OwnedObjectPath) -> Option<String> {
+    let active = active_connection(connection, path).await.ok()?;
+
+    let is_vpn = active.vpn().await.unwrap_or(false)
+        || active.type_().await.is_ok_and(|kind| kind == "wireguard");
+    if !is_vpn {
+        return None;
+    }
+
+    active.id().await.ok()
+}
+
+async fn vpn_names(
+    connection: &zbus::Connection,
+    paths: Vec<OwnedObjectPath>,
+) -> HashMap<OwnedObjectPath, String> {
+    let mut vpns = HashMap::new();
+    for path in paths {
+        if let Some(name) = vpn_name(connection, path.clone()).await {
+            vpns.insert(path, name);
+        }
+    }
+    vpns
+}
+
+fn handle_primary_connection(event_sender: &mpsc::Sender<Event>, value: Option<NetworkConnection>) {
+    _ = event_sender.send(Event::PrimaryConnection(value));
+}
+
+fn handle_connectivity(event_sender: &mpsc::Sender<Event>, value: Connectivity) {
+    _ = event_sender.send(Event::Connectivity(value));
+}
+
+fn handle_vpn(event_sender: &mpsc::Sender<Event>, name: String, connected: bool) {
+    _ = event_sender.send(Event::Vpn(name, connected));
+}
+
+pub struct NetworkManager {
+    connection: zbus::Connection,
+}
+
+impl NetworkManager {
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+
+        // Fail early when NetworkManager isn't running.
+        NetworkManagerProxy::new(&connection).await?.state().await?;
+
+        Ok(Self { connection })
+    }
+
+    /// Sends events for changes only, the state at startup isn't announced.
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let manager = NetworkManagerProxy::new(&self.connection).await?;
+
+        {
+            let mut primary_stream = manager.receive_primary_connection_changed().await;
+            let mut primary = manager.primary_connection().await?;
+
+            let connection = self.connection.clone();
+            let event_sender = event_sender.clone();
+            tokio::spawn(async move {
+                while let Some(event) = primary_stream.next().await {
+                    let Ok(path) = event.get().await else {
+                        continue;
+                    };
+                    if path == primary {
+                        continue;
+                    }
+                    primary = path.clone();
+                    handle_primary_connection(&event_sender, describe(&connection, path).await);
+                }
+            });
+        }
+
+        {
+            let mut connectivity_stream = manager.receive_connectivity_changed().await;
+
+            let event_sender = event_sender.clone();
+            tokio::spawn(async move {
+                while let Some(event) = connectivity_stream.next().await {
+                    if let Ok(connectivity) = event.get().await {
+                        handle_connectivity(&event_sender, connectivity);
+                    }
+                }
+            });
+        }
+
+        let mut active_stream = manager.receive_active_connections_changed().await;
+        let mut vpns = vpn_names(&self.connection, manager.active_connections().await?).await;
+
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            while let Some(event) = active_stream.next().await {
+                let Ok(paths) = event.get().await else {
+                    continue;
+                };
+
+                vpns.retain(|path, name| {
+                    let active = paths.contains(path);
+                    if !active {
+                        handle_vpn(&event_sender, name.clone(), false);
+                    }
+                    active
+                });
+
+                for path in paths {
+                    if vpns.contains_key(&path) {
+                        continue;
+                    }
+                    if let Some(name) = vpn_name(&connection, path.clone()).await {
+                        handle_vpn(&event_sender, name.clone(), true);
+                        vpns.insert(path, name);
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -1,4 +1,22 @@
-use std::collections::HashMap;
+use crate::Event;
+use anyhow::anyhow;
+use futures_lite::StreamExt;
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    time::Duration,
+};
+use zbus::{DBusError, names::BusName};
+
+const SERVER_NAME: &str = "org.freedesktop.Notifications";
+
+/// How long the notification server gets to answer before it is considered
+/// hung, as a blocked call would stall the whole event loop.
+const CALL_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[zbus::proxy(
     interface = "org.freedesktop.Notifications",
@@ -18,6 +36,29 @@ trait Notifications {
         hints: HashMap<&str, zbus::zvariant::Value<'_>>,
         expire_timeout: i32,
     ) -> zbus::Result<u32>;
+
+    fn close_notification(&self, id: u32) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn action_invoked(&self, id: u32, action_key: &str) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn notification_closed(&self, id: u32, reason: u32) -> zbus::Result<()>;
+}
+
+/// Whether `error` means nothing serves `org.freedesktop.Notifications`, as on
+/// bare window manager setups without mako or dunst.
+fn no_server(error: &zbus::Error) -> bool {
+    let name = match error {
+        zbus::Error::MethodError(name, _, _) => name.to_string(),
+        zbus::Error::FDO(e) => e.name().to_string(),
+        _ => return false,
+    };
+
+    matches!(
+        name.as_str(),
+        "org.freedesktop.DBus.Error.ServiceUnknown" | "org.freedesktop.DBus.Error.NameHasNoOwner"
+    ) || name.starts_with("org.freedesktop.DBus.Error.Spawn.")
 }
 
 #[derive(Clone, Copy)]
@@ -29,23 +70,36 @@ pub enum Urgency {
 
 #[derive(Clone)]
 pub struct NotificationBuilder<'a> {
+    conn: zbus::Connection,
     summary: &'a str,
     body: &'a str,
     progress: Option<i32>,
     icon: &'a str,
+    image: Option<&'a str>,
     urgency: Urgency,
     id: u32,
+    timeout: i32,
+    actions: Vec<&'a str>,
+    /// Whether `org.freedesktop.Notifications` has, or can be started with,
+    /// an owner, shared by all clones.
+    server: Arc<AtomicBool>,
 }
 
-pub async fn notify<'a>() -> zbus::Result<NotificationBuilder<'a>> {
-    Ok(NotificationBuilder {
+/// A builder talking to the notification server on `conn`.
+pub fn notify<'a>(conn: zbus::Connection) -> NotificationBuilder<'a> {
+    NotificationBuilder {
+        conn,
         summary: "",
         body: "",
         progress: None,
         icon: "",
+        image: None,
         urgency: Urgency::Low,
         id: 0,
-    })
+        timeout: -1,
+        actions: Vec::new(),
+        server: Arc::new(AtomicBool::new(true)),
+    }
 }
 
 impl<'a> NotificationBuilder<'a> {
@@ -64,6 +118,12 @@ impl<'a> NotificationBuilder<'a> {
         self
     }
 
+    /// Image shown instead of the icon, a path or `file://` URI.
+    pub fn with_image(mut self, image: &'a str) -> Self {
+        self.image = Some(image);
+        self
+    }
+
     pub fn with_body(mut self, body: &'a str) -> Self {
         self.body = body;
         self
@@ -79,27 +139,244 @@ impl<'a> NotificationBuilder<'a> {
         self
     }
 
-    pub async fn send(&self) -> zbus::Result<u32> {
-        let conn = zbus::Connection::session().await?;
-        let proxy = NotificationsProxy::new(&conn).await?;
+    pub fn with_action(mut self, key: &'a str, label: &'a str) -> Self {
+        self.actions.extend([key, label]);
+        self
+    }
+
+    /// Expiration timeout in milliseconds, 0 never expires and -1 leaves it to the server.
+    pub fn with_timeout(mut self, timeout: i32) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sends the notification, returning its id, or 0 when it was logged to
+    /// stderr because no notification server is running.
+    pub async fn send(&self) -> anyhow::Result<u32> {
+        if !self.server.load(Ordering::Relaxed) {
+            eprintln!("{}", self.log_line());
+            return Ok(0);
+        }
+
+        let proxy = NotificationsProxy::new(&self.conn).await?;
 
         let mut hints = HashMap::new();
         hints.insert("urgency", zbus::zvariant::Value::U8(self.urgency as u8));
         if let Some(value) = self.progress {
             hints.insert("value", zbus::zvariant::Value::I32(value));
         }
+        if let Some(image) = self.image {
+            hints.insert("image-path", zbus::zvariant::Value::from(image));
+        }
 
-        proxy
-            .notify(
+        let sent = tokio::time::timeout(
+            CALL_TIMEOUT,
+            proxy.notify(
                 "SysNotifier",
                 self.id,
                 self.icon,
                 self.summary,
                 self.body,
-                &[],
+                &self.actions,
                 hints,
-                -1,
-            )
+                self.timeout,
+            ),
+        )
+        .await
+        .map_err(|_| anyhow!("Notification server didn't answer in time"))?;
+        match sent {
+            Ok(id) => Ok(id),
+            // The server went away before its owner change was seen.
+            Err(e) if no_server(&e) => {
+                self.server.store(false, Ordering::Relaxed);
+                eprintln!("{}", self.log_line());
+                Ok(0)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The notification as printed to stderr instead, which ends up in the
+    /// journal when running as a systemd user service.
+    fn log_line(&self) -> String {
+        let mut line = self.summary.to_string();
+        if !self.body.is_empty() {
+            line = format!("{line}: {}", self.body.replace('\n', ", "));
+        }
+        format!("Notification: {line}")
+    }
+
+    pub async fn close(&self, id: u32) -> anyhow::Result<()> {
+        if !self.server.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let proxy = NotificationsProxy::new(&self.conn).await?;
+        let closed = tokio::time::timeout(CALL_TIMEOUT, proxy.close_notification(id))
             .await
+            .map_err(|_| anyhow!("Notification server didn't answer in time"))?;
+        match closed {
+            Err(e) if no_server(&e) => Ok(()),
+            closed => Ok(closed?),
+        }
+    }
+
+    /// Tracks whether a notification server runs on the bus, so that
+    /// notifications go to stderr instead while there is none.
+    pub async fn watch_server(&self) -> anyhow::Result<()> {
+        let dbus = zbus::fdo::DBusProxy::new(&self.conn).await?;
+        let mut owner_changes = dbus
+            .receive_name_owner_changed_with_args(&[(0, SERVER_NAME)])
+            .await?;
+
+        // An activatable server is started by the first call.
+        let activatable = dbus
+            .list_activatable_names()
+            .await?
+            .iter()
+            .any(|name| name.as_str() == SERVER_NAME);
+        let owned = dbus.name_has_owner(BusName::try_from(SERVER_NAME)?).await?;
+        self.server.store(owned || activatable, Ordering::Relaxed);
+
+        let server = Arc::clone(&self.server);
+        tokio::spawn(async move {
+            while let Some(signal) = owner_changes.next().await {
+                if let Ok(args) = signal.args() {
+                    server.store(args.new_owner.is_some() || activatable, Ordering::Relaxed);
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Forwards action clicks on any notification as [`Event::ActionInvoked`]
+    /// and closed notifications as [`Event::NotificationClosed`].
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let proxy = NotificationsProxy::new(&self.conn).await?;
+        let mut actions = proxy.receive_action_invoked().await?;
+        let mut closes = proxy.receive_notification_closed().await?;
+
+        let action_sender = event_sender.clone();
+        tokio::spawn(async move {
+            while let Some(signal) = actions.next().await {
+                if let Ok(args) = signal.args() {
+                    _ = action_sender.send(Event::ActionInvoked(args.id, args.action_key.into()));
+                }
+            }
+        });
+
+        tokio::spawn(async move {
+            while let Some(signal) = closes.next().await {
+                if let Ok(args) = signal.args() {
+                    _ = event_sender.send(Event::NotificationClosed(args.id));
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::FakeNotifications;
+    use tokio::net::UnixStream;
+    use zbus::names::OwnedErrorName;
+
+    fn method_error(name: &str) -> anyhow::Result<zbus::Error> {
+        let reply =
+            zbus::Message::method_call("/org/freedesktop/Notifications", "Notify")?.build(&())?;
+        Ok(zbus::Error::MethodError(
+            OwnedErrorName::try_from(name)?,
+            None,
+            reply,
+        ))
+    }
+
+    #[test]
+    fn missing_servers_are_recognized() -> anyhow::Result<()> {
+        let cases = [
+            ("org.freedesktop.DBus.Error.ServiceUnknown", true),
+            ("org.freedesktop.DBus.Error.NameHasNoOwner", true),
+            ("org.freedesktop.DBus.Error.Spawn.ChildExited", true),
+            ("org.freedesktop.DBus.Error.NoReply", false),
+            ("org.freedesktop.DBus.Error.AccessDenied", false),
+        ];
+
+        for (name, missing) in cases {
+            assert_eq!(no_server(&method_error(name)?), missing, "{name}");
+        }
+
+        let fdo = |e| zbus::Error::FDO(Box::new(e));
+        assert!(no_server(&fdo(zbus::fdo::Error::ServiceUnknown(
+            "no owner".into()
+        ))));
+        assert!(!no_server(&fdo(zbus::fdo::Error::NoReply(
+            "timed out".into()
+        ))));
+        assert!(!no_server(&zbus::Error::InvalidReply));
+        Ok(())
+    }
+
+    async fn peer(path: &str) -> anyhow::Result<(zbus::Connection, zbus::Connection)> {
+        let (server, client) = UnixStream::pair()?;
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())?
+            .p2p()
+            .serve_at(path, FakeNotifications::default())?
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        Ok(futures_lite::future::try_zip(server, client).await?)
+    }
+
+    #[tokio::test]
+    async fn notifications_are_logged_without_a_server() -> anyhow::Result<()> {
+        let (_server, client) = peer("/org/freedesktop/Notifications").await?;
+
+        let builder = notify(client)
+            .with_summary("Battery level low")
+            .with_body("Current battery percentage: 15%\n30m remaining");
+        builder.server.store(false, Ordering::Relaxed);
+
+        assert_eq!(builder.send().await?, 0);
+        assert_eq!(
+            builder.log_line(),
+            "Notification: Battery level low: Current battery percentage: 15%, 30m remaining"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_returned() -> anyhow::Result<()> {
+        // A peer serving something else answers with an UnknownObject error.
+        let (_server, client) = peer("/elsewhere").await?;
+
+        let builder = notify(client).with_summary("Battery level low");
+
+        assert!(builder.send().await.is_err());
+        assert!(builder.server.load(Ordering::Relaxed));
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hung_servers_time_out() -> anyhow::Result<()> {
+        // A peer without an object server never replies.
+        let (server, client) = UnixStream::pair()?;
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(zbus::Guid::generate())?
+            .p2p()
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (_server, client) = futures_lite::future::try_zip(server, client).await?;
+
+        let error = notify(client)
+            .with_summary("Battery level low")
+            .send()
+            .await;
+
+        assert!(error.unwrap_err().to_string().contains("in time"));
+        Ok(())
     }
 }
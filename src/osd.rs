@@ -0,0 +1,146 @@
+use crate::audio::Volume;
+use crate::config::OsdConfig;
+use rustix::fs::OFlags;
+use serde::Deserialize;
+use std::{fs::OpenOptions, io::Write, os::unix::fs::OpenOptionsExt, path::PathBuf};
+
+/// Values that can be shown on a bar OSD instead of a notification.
+#[derive(Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum OsdSource {
+    Volume,
+    Brightness,
+}
+
+/// A bar OSD such as wob, fed `<value> [style]` lines through a FIFO.
+pub struct Osd {
+    config: OsdConfig,
+    path: PathBuf,
+}
+
+impl Osd {
+    /// `None` when nothing is routed to the OSD.
+    pub fn new(config: OsdConfig) -> anyhow::Result<Option<Self>> {
+        if config.show.is_empty() {
+            return Ok(None);
+        }
+
+        let path = match &config.path {
+            Some(path) => path.clone(),
+            None => std::env::var_os("XDG_RUNTIME_DIR")
+                .map(|runtime_dir| PathBuf::from(runtime_dir).join("wob.sock"))
+                .ok_or_else(|| anyhow::anyhow!("XDG_RUNTIME_DIR is not set, set [osd] path"))?,
+        };
+
+        Ok(Some(Self { config, path }))
+    }
+
+    pub fn shows(&self, source: OsdSource) -> bool {
+        self.config.show.contains(&source)
+    }
+
+    pub fn show_volume(&self, volume: &Volume) -> anyhow::Result<()> {
+        let style = if volume.muted {
+            self.config.muted_style.as_deref()
+        } else if volume.value > 100 {
+            self.config.overflow_style.as_deref()
+        } else {
+            None
+        };
+
+        // Without a style of its own, mute reads as an empty bar.
+        let value = match volume.muted && style.is_none() {
+            true => 0,
+            false => volume.value,
+        };
+        self.write(value, style)
+    }
+
+    pub fn show_brightness(&self, percentage: u32) -> anyhow::Result<()> {
+        self.write(percentage, None)
+    }
+
+    /// Fails instead of blocking when nothing reads the FIFO, e.g. wob isn't
+    /// running, so that the caller can fall back to a notification.
+    fn write(&self, value: u32, style: Option<&str>) -> anyhow::Result<()> {
+        let mut fifo = OpenOptions::new()
+            .append(true)
+            .custom_flags(OFlags::NONBLOCK.bits() as i32)
+            .open(&self.path)?;
+
+        let line = match style {
+            Some(style) => format!("{value} {style}\n"),
+            None => format!("{value}\n"),
+        };
+        fifo.write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An OSD writing to a regular file standing in for the FIFO.
+    fn osd(name: &str, muted_style: Option<&str>) -> anyhow::Result<Osd> {
+        let path =
+            std::env::temp_dir().join(format!("sysnotifier-osd-{name}-{}", std::process::id()));
+        std::fs::write(&path, "")?;
+
+        let config = OsdConfig {
+            show: vec![OsdSource::Volume],
+            path: Some(path),
+            muted_style: muted_style.map(String::from),
+            overflow_style: Some("overflow".into()),
+        };
+        Ok(Osd::new(config)?.expect("volume is routed to the OSD"))
+    }
+
+    fn written(osd: &Osd) -> anyhow::Result<String> {
+        let contents = std::fs::read_to_string(&osd.path)?;
+        std::fs::remove_file(&osd.path)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn volume_lines_carry_styles() -> anyhow::Result<()> {
+        let osd = osd("styles", Some("muted"))?;
+
+        for (value, muted) in [(40, false), (40, true), (120, false)] {
+            osd.show_volume(&Volume { value, muted })?;
+        }
+        osd.show_brightness(75)?;
+
+        assert_eq!(written(&osd)?, "40\n40 muted\n120 overflow\n75\n");
+        Ok(())
+    }
+
+    #[test]
+    fn mute_without_style_empties_the_bar() -> anyhow::Result<()> {
+        let osd = osd("unstyled", None)?;
+
+        osd.show_volume(&Volume {
+            value: 40,
+            muted: true,
+        })?;
+
+        assert_eq!(written(&osd)?, "0\n");
+        Ok(())
+    }
+
+    #[test]
+    fn missing_fifo_fails() -> anyhow::Result<()> {
+        let osd = osd("missing", None)?;
+        std::fs::remove_file(&osd.path)?;
+
+        assert!(osd.show_brightness(50).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn nothing_routed_means_no_osd() -> anyhow::Result<()> {
+        assert!(Osd::new(OsdConfig::default())?.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,617 @@
+use crate::Event;
+use crate::audio::{AudioBackend, AudioRoutes, CardProfile, SinkPort, Volume};
+use pipewire as pw;
+use pw::{
+    device::Device,
+    metadata::Metadata,
+    node::{Node, NodeState},
+    proxy::{Listener, ProxyT},
+    spa::{
+        param::ParamType,
+        pod::{Pod, Value, ValueArray, deserialize::PodDeserializer},
+        sys as spa_sys,
+    },
+    types::ObjectType,
+};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    convert::Infallible,
+    rc::Rc,
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+
+/// How long to wait before connecting again after losing PipeWire, e.g.
+/// while it restarts.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// An audio node as seen on the PipeWire graph.
+#[derive(Default)]
+struct AudioNode {
+    name: String,
+    /// `node.description`, e.g. "Built-in Audio Analog Stereo".
+    description: String,
+    class: String,
+    /// `application.name` of streams.
+    application: Option<String>,
+    /// Streams capturing a sink monitor rather than a microphone.
+    monitor: bool,
+    running: bool,
+    volume: Option<Volume>,
+    /// Device id and `card.profile.device` of sinks, which pick the device
+    /// route they play through.
+    route: Option<(u32, i32)>,
+}
+
+/// The active route of a device, e.g. speakers or headphones.
+struct Route {
+    name: String,
+    description: String,
+}
+
+/// Graph state mirrored from the PipeWire thread.
+#[derive(Default)]
+struct Graph {
+    nodes: HashMap<u32, AudioNode>,
+    default_sink: Option<String>,
+    default_source: Option<String>,
+    /// Active profiles by device id.
+    profiles: HashMap<u32, CardProfile>,
+    /// Active output routes by device id and profile device.
+    routes: HashMap<(u32, i32), Route>,
+    /// Where to send events once subscribed, and whether recordings are wanted.
+    subscriber: Option<(mpsc::Sender<Event>, bool)>,
+}
+
+impl Graph {
+    fn send(&self, event: Event) {
+        if let Some((event_sender, _)) = &self.subscriber {
+            _ = event_sender.send(event);
+        }
+    }
+
+    fn send_recordings(&self) {
+        if let Some((event_sender, true)) = &self.subscriber {
+            _ = event_sender.send(Event::SourceOutputsChanged);
+        }
+    }
+
+    fn default_node(&self, name: Option<&str>, kind: &str) -> anyhow::Result<&AudioNode> {
+        let name = name.ok_or_else(|| anyhow::anyhow!("No default {kind}"))?;
+        self.nodes
+            .values()
+            .find(|node| node.name == name)
+            .ok_or_else(|| anyhow::anyhow!("{kind} not found"))
+    }
+
+    fn default_volume(&self, name: Option<&str>, kind: &str) -> anyhow::Result<Volume> {
+        self.default_node(name, kind)?
+            .volume
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("{kind} has no volume"))
+    }
+
+    /// Profiles by device id and ports by sink node id, like the card and
+    /// sink indices of PulseAudio.
+    fn audio_routes(&self) -> AudioRoutes {
+        let ports = self
+            .nodes
+            .iter()
+            .filter_map(|(id, node)| {
+                let route = self.routes.get(node.route.as_ref()?)?;
+                let port = SinkPort {
+                    sink: node.description.clone(),
+                    name: route.name.clone(),
+                    description: route.description.clone(),
+                };
+                Some((*id, port))
+            })
+            .collect();
+
+        AudioRoutes {
+            profiles: self.profiles.clone(),
+            ports,
+        }
+    }
+}
+
+/// Properties of an object param such as `Props` or `Route`, by key.
+fn param_values(pod: &Pod) -> HashMap<u32, Value> {
+    match PodDeserializer::deserialize_any_from(pod.as_bytes()) {
+        Ok((_, Value::Object(object))) => object
+            .properties
+            .into_iter()
+            .map(|property| (property.key, property.value))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+fn string(values: &HashMap<u32, Value>, key: u32) -> String {
+    match values.get(&key) {
+        Some(Value::String(value)) => value.clone(),
+        _ => String::new(),
+    }
+}
+
+/// The loudest channel, PipeWire volumes are cubic so they match the
+/// percentages shown by PulseAudio tools.
+fn props_volume(values: &HashMap<u32, Value>) -> Option<Volume> {
+    let Some(Value::ValueArray(ValueArray::Float(channels))) =
+        values.get(&spa_sys::SPA_PROP_channelVolumes)
+    else {
+        return None;
+    };
+    let loudest = channels.iter().copied().fold(0.0f32, f32::max);
+
+    Some(Volume {
+        value: (loudest.cbrt() * 100.0).round() as u32,
+        muted: matches!(values.get(&spa_sys::SPA_PROP_mute), Some(Value::Bool(true))),
+    })
+}
+
+/// The node name from a `default.audio.*` metadata value, e.g.
+/// `{"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"}`.
+fn metadata_name(value: &str) -> Option<String> {
+    let (_, rest) = value.split_once("\"name\"")?;
+    let (_, rest) = rest.split_once('"')?;
+    let (name, _) = rest.split_once('"')?;
+    Some(name.to_string())
+}
+
+/// Proxies and their listeners, which stop working once dropped.
+type Proxies = HashMap<u32, (Box<dyn ProxyT>, Vec<Box<dyn Listener>>)>;
+
+fn bind_node(
+    registry: &pw::registry::Registry,
+    global: &pw::registry::GlobalObject<&pw::spa::utils::dict::DictRef>,
+    graph: &Arc<Mutex<Graph>>,
+) -> Option<(Box<dyn ProxyT>, Vec<Box<dyn Listener>>)> {
+    let class = global.props?.get(*pw::keys::MEDIA_CLASS)?.to_string();
+    if !matches!(
+        class.as_str(),
+        "Audio/Sink" | "Audio/Source" | "Stream/Input/Audio"
+    ) {
+        return None;
+    }
+
+    let node: Node = registry.bind(global).ok()?;
+    let id = global.id;
+    graph.lock().unwrap().nodes.insert(
+        id,
+        AudioNode {
+            class,
+            ..AudioNode::default()
+        },
+    );
+
+    let info_graph = Arc::clone(graph);
+    let param_graph = Arc::clone(graph);
+    let listener = node
+        .add_listener_local()
+        .info(move |info| {
+            let mut graph = info_graph.lock().unwrap();
+            let Some(node) = graph.nodes.get_mut(&id) else {
+                return;
+            };
+            let props = info.props();
+            let prop = |key: &str| props.and_then(|props| props.get(key)).map(String::from);
+
+            node.name = prop(*pw::keys::NODE_NAME).unwrap_or_default();
+            node.description = prop(*pw::keys::NODE_DESCRIPTION).unwrap_or_default();
+            node.application = prop(*pw::keys::APP_NAME);
+            node.monitor = prop("stream.capture.sink").as_deref() == Some("true");
+            let device = prop("device.id").and_then(|id| id.parse().ok());
+            let profile_device = prop("card.profile.device").and_then(|id| id.parse().ok());
+            node.route = device
+                .zip(profile_device)
+                .filter(|_| node.class == "Audio/Sink");
+            let running = matches!(info.state(), NodeState::Running);
+            let stream = node.class == "Stream/Input/Audio" && !node.monitor;
+            let changed = node.running != running;
+            node.running = running;
+
+            if stream && changed {
+                graph.send_recordings();
+            }
+        })
+        .param(move |_, _, _, _, pod| {
+            let Some(volume) = pod.map(param_values).as_ref().and_then(props_volume) else {
+                return;
+            };
+            let mut graph = param_graph.lock().unwrap();
+            let Some(node) = graph.nodes.get_mut(&id) else {
+                return;
+            };
+            node.volume = Some(volume);
+            let event = match node.class.as_str() {
+                "Audio/Sink" => Event::VolumeChanged,
+                "Audio/Source" => Event::SourceVolumeChanged,
+                _ => return,
+            };
+            graph.send(event);
+        })
+        .register();
+    node.subscribe_params(&[ParamType::Props]);
+
+    Some((Box::new(node), vec![Box::new(listener)]))
+}
+
+fn bind_device(
+    registry: &pw::registry::Registry,
+    global: &pw::registry::GlobalObject<&pw::spa::utils::dict::DictRef>,
+    graph: &Arc<Mutex<Graph>>,
+) -> Option<(Box<dyn ProxyT>, Vec<Box<dyn Listener>>)> {
+    let props = global.props?;
+    if props.get("media.class") != Some("Audio/Device") {
+        return None;
+    }
+    let card = props
+        .get("device.description")
+        .or_else(|| props.get("device.name"))
+        .unwrap_or_default()
+        .to_string();
+
+    let device: Device = registry.bind(global).ok()?;
+    let id = global.id;
+    let graph = Arc::clone(graph);
+    let listener = device
+        .add_listener_local()
+        .param(move |_, kind, _, _, pod| {
+            let Some(values) = pod.map(param_values) else {
+                return;
+            };
+            let mut graph = graph.lock().unwrap();
+
+            if kind == ParamType::Profile {
+                let profile = CardProfile {
+                    card: card.clone(),
+                    name: string(&values, spa_sys::SPA_PARAM_PROFILE_name),
+                    description: string(&values, spa_sys::SPA_PARAM_PROFILE_description),
+                };
+                graph.profiles.insert(id, profile);
+            } else if kind == ParamType::Route {
+                let output = matches!(
+                    values.get(&spa_sys::SPA_PARAM_ROUTE_direction),
+                    Some(Value::Id(direction)) if direction.0 == spa_sys::SPA_DIRECTION_OUTPUT
+                );
+                let Some(&Value::Int(profile_device)) =
+                    values.get(&spa_sys::SPA_PARAM_ROUTE_device)
+                else {
+                    return;
+                };
+                if !output {
+                    return;
+                }
+                let route = Route {
+                    name: string(&values, spa_sys::SPA_PARAM_ROUTE_name),
+                    description: string(&values, spa_sys::SPA_PARAM_ROUTE_description),
+                };
+                graph.routes.insert((id, profile_device), route);
+            } else {
+                return;
+            }
+            graph.send(Event::AudioRoutesChanged);
+        })
+        .register();
+    device.subscribe_params(&[ParamType::Profile, ParamType::Route]);
+
+    Some((Box::new(device), vec![Box::new(listener)]))
+}
+
+fn bind_metadata(
+    registry: &pw::registry::Registry,
+    global: &pw::registry::GlobalObject<&pw::spa::utils::dict::DictRef>,
+    graph: &Arc<Mutex<Graph>>,
+) -> Option<(Box<dyn ProxyT>, Vec<Box<dyn Listener>>)> {
+    if global.props?.get("metadata.name") != Some("default") {
+        return None;
+    }
+
+    let metadata: Metadata = registry.bind(global).ok()?;
+    let graph = Arc::clone(graph);
+    let listener = metadata
+        .add_listener_local()
+        .property(move |_, key, _, value| {
+            let mut graph = graph.lock().unwrap();
+            let name = value.and_then(metadata_name);
+            match key {
+                Some("default.audio.sink") => {
+                    graph.default_sink = name;
+                    graph.send(Event::VolumeChanged);
+                }
+                Some("default.audio.source") => {
+                    graph.default_source = name;
+                    graph.send(Event::SourceVolumeChanged);
+                }
+                _ => {}
+            }
+            0
+        })
+        .register();
+
+    Some((Box::new(metadata), vec![Box::new(listener)]))
+}
+
+/// Reports whether the first connection succeeded, taken once reported.
+type Ready = Rc<Cell<Option<mpsc::Sender<anyhow::Result<()>>>>>;
+
+/// Runs the PipeWire main loop, reporting on `ready` once the initial state of
+/// the graph has been received, until disconnected.
+fn run(graph: Arc<Mutex<Graph>>, ready: Ready) -> anyhow::Result<Infallible> {
+    pw::init();
+    let main_loop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&main_loop)?;
+    let core = context.connect(None)?;
+    let registry = Rc::new(core.get_registry()?);
+    let proxies = Rc::new(RefCell::new(Proxies::new()));
+
+    let registry_weak = Rc::downgrade(&registry);
+    let global_proxies = Rc::clone(&proxies);
+    let global_graph = Arc::clone(&graph);
+    let remove_graph = Arc::clone(&graph);
+    let _registry_listener = registry
+        .add_listener_local()
+        .global(move |global| {
+            let Some(registry) = registry_weak.upgrade() else {
+                return;
+            };
+            let bound = match global.type_ {
+                ObjectType::Node => bind_node(&registry, global, &global_graph),
+                ObjectType::Device => bind_device(&registry, global, &global_graph),
+                ObjectType::Metadata => bind_metadata(&registry, global, &global_graph),
+                _ => None,
+            };
+            if let Some(bound) = bound {
+                global_proxies.borrow_mut().insert(global.id, bound);
+            }
+        })
+        .global_remove(move |id| {
+            if proxies.borrow_mut().remove(&id).is_none() {
+                return;
+            }
+            let mut graph = remove_graph.lock().unwrap();
+            let stream = graph.nodes.remove(&id).is_some_and(|node| {
+                node.class == "Stream/Input/Audio" && node.running && !node.monitor
+            });
+            let routes = graph.routes.len();
+            graph.routes.retain(|(device, _), _| *device != id);
+            let device = graph.profiles.remove(&id).is_some() | (graph.routes.len() != routes);
+            if stream {
+                graph.send_recordings();
+            }
+            if device {
+                graph.send(Event::AudioRoutesChanged);
+            }
+        })
+        .register();
+
+    // The first sync is done once every existing global has been announced,
+    // but the binds and param subscriptions made while handling them are only
+    // sent then. Replies arrive in order, so a second sync sent after the
+    // first one is done once their initial params have been seen too.
+    let pending = Cell::new(core.sync(0)?);
+    let first_round = Cell::new(true);
+    let sync_core = core.clone();
+    let main_loop_weak = main_loop.downgrade();
+    let _core_listener = core
+        .add_listener_local()
+        .done(move |id, seq| {
+            if id != pw::core::PW_ID_CORE || seq != pending.get() {
+                return;
+            }
+            if !first_round.replace(false) {
+                if let Some(ready) = ready.take() {
+                    _ = ready.send(Ok(()));
+                }
+                return;
+            }
+            match sync_core.sync(0) {
+                Ok(seq) => pending.set(seq),
+                Err(e) => {
+                    if let Some(ready) = ready.take() {
+                        _ = ready.send(Err(e.into()));
+                    }
+                }
+            }
+        })
+        .error(move |id, _, _, message| {
+            eprintln!("PipeWire error: {message}");
+            if id == pw::core::PW_ID_CORE
+                && let Some(main_loop) = main_loop_weak.upgrade()
+            {
+                main_loop.quit();
+            }
+        })
+        .register();
+
+    main_loop.run();
+    anyhow::bail!("Disconnected from PipeWire")
+}
+
+pub struct PipeWireManager {
+    graph: Arc<Mutex<Graph>>,
+}
+
+impl PipeWireManager {
+    pub fn new() -> anyhow::Result<Self> {
+        let graph = Arc::new(Mutex::new(Graph::default()));
+        let (ready_sender, ready) = mpsc::channel();
+
+        // PipeWire objects can't leave the thread running their loop.
+        std::thread::spawn({
+            let graph = Arc::clone(&graph);
+            move || {
+                let ready = Rc::new(Cell::new(Some(ready_sender)));
+                loop {
+                    let Err(e) = run(Arc::clone(&graph), Rc::clone(&ready));
+                    // Never connected, the PulseAudio backend is used instead.
+                    if let Some(ready) = ready.take() {
+                        _ = ready.send(Err(e));
+                        return;
+                    }
+                    eprintln!("{e}, reconnecting");
+
+                    // Globals are announced again once reconnected.
+                    let mut graph = graph.lock().unwrap();
+                    *graph = Graph {
+                        subscriber: graph.subscriber.take(),
+                        ..Graph::default()
+                    };
+                    drop(graph);
+                    std::thread::sleep(RECONNECT_DELAY);
+                }
+            }
+        });
+
+        ready.recv()??;
+        Ok(Self { graph })
+    }
+}
+
+impl AudioBackend for PipeWireManager {
+    fn default_sink_volume(&mut self) -> anyhow::Result<Volume> {
+        let graph = self.graph.lock().unwrap();
+        graph.default_volume(graph.default_sink.as_deref(), "sink")
+    }
+
+    fn default_source_volume(&mut self) -> anyhow::Result<Volume> {
+        let graph = self.graph.lock().unwrap();
+        graph.default_volume(graph.default_source.as_deref(), "source")
+    }
+
+    fn default_sink_description(&mut self) -> anyhow::Result<String> {
+        let graph = self.graph.lock().unwrap();
+        let sink = graph.default_node(graph.default_sink.as_deref(), "sink")?;
+        Ok(sink.description.clone())
+    }
+
+    fn recording_applications(&mut self) -> anyhow::Result<Vec<String>> {
+        let graph = self.graph.lock().unwrap();
+        let mut applications: Vec<_> = graph
+            .nodes
+            .values()
+            .filter(|node| node.class == "Stream/Input/Audio" && node.running && !node.monitor)
+            .map(|node| {
+                node.application
+                    .clone()
+                    .unwrap_or_else(|| "an unknown application".into())
+            })
+            .collect();
+        applications.sort();
+        applications.dedup();
+        Ok(applications)
+    }
+
+    fn audio_routes(&mut self) -> anyhow::Result<AudioRoutes> {
+        Ok(self.graph.lock().unwrap().audio_routes())
+    }
+
+    fn subscribe(&mut self, event_sender: mpsc::Sender<Event>, recordings: bool) {
+        self.graph.lock().unwrap().subscriber = Some((event_sender, recordings));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(channels: &[f32], mute: bool) -> HashMap<u32, Value> {
+        HashMap::from([
+            (
+                spa_sys::SPA_PROP_channelVolumes,
+                Value::ValueArray(ValueArray::Float(channels.to_vec())),
+            ),
+            (spa_sys::SPA_PROP_mute, Value::Bool(mute)),
+        ])
+    }
+
+    #[test]
+    fn props_volume_is_cubic_and_loudest() {
+        let cases = [
+            (&[1.0, 1.0][..], false, 100, false),
+            (&[0.125, 0.125], false, 50, false),
+            // Balance doesn't lower the volume shown.
+            (&[0.125, 0.064], false, 50, false),
+            (&[0.0, 0.0], true, 0, true),
+            // Over-amplified.
+            (&[3.375], false, 150, false),
+        ];
+
+        for (channels, mute, value, muted) in cases {
+            assert_eq!(
+                props_volume(&props(channels, mute)),
+                Some(Volume { value, muted }),
+                "{channels:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn props_without_volumes_are_ignored() {
+        let values = HashMap::from([(spa_sys::SPA_PROP_mute, Value::Bool(true))]);
+
+        assert_eq!(props_volume(&values), None);
+    }
+
+    #[test]
+    fn ports_are_keyed_by_sink() {
+        let sink = |description: &str, route| AudioNode {
+            description: description.into(),
+            class: "Audio/Sink".into(),
+            route,
+            ..AudioNode::default()
+        };
+        let route = |name: &str, description: &str| Route {
+            name: name.into(),
+            description: description.into(),
+        };
+        let graph = Graph {
+            nodes: HashMap::from([
+                (58, sink("Built-in Audio Analog Stereo", Some((47, 1)))),
+                (59, sink("Built-in Audio HDMI", Some((47, 3)))),
+                // Its route hasn't been seen yet.
+                (60, sink("USB Audio", Some((52, 1)))),
+            ]),
+            routes: HashMap::from([
+                ((47, 1), route("analog-output-headphones", "Headphones")),
+                ((47, 3), route("hdmi-output-0", "HDMI / DisplayPort")),
+            ]),
+            ..Graph::default()
+        };
+
+        let ports = graph.audio_routes().ports;
+
+        assert_eq!(ports.len(), 2);
+        assert_eq!(
+            ports[&58],
+            SinkPort {
+                sink: "Built-in Audio Analog Stereo".into(),
+                name: "analog-output-headphones".into(),
+                description: "Headphones".into(),
+            }
+        );
+        assert_eq!(ports[&59].sink, "Built-in Audio HDMI");
+    }
+
+    #[test]
+    fn metadata_names() {
+        let cases = [
+            (
+                r#"{"name":"alsa_output.pci-0000_00_1f.3.analog-stereo"}"#,
+                Some("alsa_output.pci-0000_00_1f.3.analog-stereo"),
+            ),
+            (
+                r#"{ "name": "bluez_output.AC_80_0A_2E_5C_41.1" }"#,
+                Some("bluez_output.AC_80_0A_2E_5C_41.1"),
+            ),
+            ("", None),
+            (r#"{"other":"value"}"#, None),
+        ];
+
+        for (value, name) in cases {
+            assert_eq!(metadata_name(value).as_deref(), name, "{value}");
+        }
+    }
+}
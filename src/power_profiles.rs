@@ -0,0 +1,107 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use std::sync::mpsc;
+use zbus::proxy;
+
+#[proxy(
+    interface = "org.freedesktop.UPower.PowerProfiles",
+    default_service = "org.freedesktop.UPower.PowerProfiles",
+    default_path = "/org/freedesktop/UPower/PowerProfiles"
+)]
+trait PowerProfiles {
+    #[zbus(property)]
+    fn active_profile(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn set_active_profile(&self, profile: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn performance_degraded(&self) -> zbus::Result<String>;
+}
+
+fn handle_active_profile(event_sender: &mpsc::Sender<Event>, value: String) {
+    _ = event_sender.send(Event::PowerProfile(value));
+}
+
+fn handle_performance_degraded(event_sender: &mpsc::Sender<Event>, value: String) {
+    _ = event_sender.send(Event::PerformanceDegraded(value));
+}
+
+pub fn profile_name(profile: &str) -> &str {
+    match profile {
+        "power-saver" => "Power Saver",
+        "balanced" => "Balanced",
+        "performance" => "Performance",
+        profile => profile,
+    }
+}
+
+pub fn degraded_reason(reason: &str) -> &str {
+    match reason {
+        "lap-detected" => "The computer is sitting on a lap",
+        "high-operating-temperature" => "The computer is too hot",
+        reason => reason,
+    }
+}
+
+pub struct PowerProfilesManager {
+    proxy: PowerProfilesProxy<'static>,
+}
+
+impl PowerProfilesManager {
+    /// Connects to power-profiles-daemon, preferring the UPower bus name used since 0.20.
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+
+        let proxy = PowerProfilesProxy::new(&connection).await?;
+        if proxy.active_profile().await.is_ok() {
+            return Ok(Self { proxy });
+        }
+
+        let proxy = PowerProfilesProxy::builder(&connection)
+            .destination("net.hadess.PowerProfiles")?
+            .path("/net/hadess/PowerProfiles")?
+            .interface("net.hadess.PowerProfiles")?
+            .build()
+            .await?;
+        proxy.active_profile().await?;
+
+        Ok(Self { proxy })
+    }
+
+    pub async fn active_profile(&self) -> anyhow::Result<String> {
+        Ok(self.proxy.active_profile().await?)
+    }
+
+    pub async fn set_active_profile(&self, profile: &str) -> anyhow::Result<()> {
+        self.proxy.set_active_profile(profile).await?;
+        Ok(())
+    }
+
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        {
+            let mut profile_stream = self.proxy.receive_active_profile_changed().await;
+
+            let event_sender = event_sender.clone();
+            tokio::spawn(async move {
+                while let Some(event) = profile_stream.next().await {
+                    if let Ok(profile) = event.get().await {
+                        handle_active_profile(&event_sender, profile);
+                    }
+                }
+            });
+        }
+
+        let mut degraded_stream = self.proxy.receive_performance_degraded_changed().await;
+
+        tokio::spawn(async move {
+            while let Some(event) = degraded_stream.next().await {
+                if let Ok(reason) = event.get().await {
+                    handle_performance_degraded(&event_sender, reason);
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
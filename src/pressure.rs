@@ -0,0 +1,397 @@
+use crate::Event;
+use crate::config::PressureConfig;
+use futures_lite::StreamExt;
+use rustix::event::{PollFd, PollFlags, poll};
+use serde::Serialize;
+use std::{
+    cmp::Reverse,
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+use zbus::proxy;
+
+/// Window of the PSI triggers, unprivileged triggers need a multiple of 2s.
+const TRIGGER_WINDOW_US: u64 = 2_000_000;
+
+/// How many processes to name in a memory pressure notification.
+const TOP_CONSUMERS: usize = 3;
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Resource {
+    Memory,
+    Cpu,
+    Io,
+}
+
+impl Resource {
+    fn file(&self) -> &'static str {
+        match self {
+            Resource::Memory => "/proc/pressure/memory",
+            Resource::Cpu => "/proc/pressure/cpu",
+            Resource::Io => "/proc/pressure/io",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resource::Memory => "Memory",
+            Resource::Cpu => "CPU",
+            Resource::Io => "I/O",
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Process {
+    pub pid: u32,
+    pub name: String,
+    /// Resident set size in kB.
+    pub rss: u64,
+}
+
+/// Pressure above the configured threshold.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Pressure {
+    pub resource: Resource,
+    /// Share of the last 10s some tasks were stalled, in percent.
+    pub avg10: u32,
+    /// Largest memory consumers, only filled in for memory pressure.
+    pub top: Vec<Process>,
+}
+
+/// The `some avg10=` value of a PSI file.
+fn avg10(resource: Resource) -> Option<f64> {
+    parse_avg10(&fs::read_to_string(resource.file()).ok()?)
+}
+
+fn parse_avg10(contents: &str) -> Option<f64> {
+    let some = contents.lines().find(|line| line.starts_with("some "))?;
+    some.split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))?
+        .parse()
+        .ok()
+}
+
+fn process(status: &str, pid: u32) -> Option<Process> {
+    let mut name = None;
+    let mut rss = None;
+    for line in status.lines() {
+        if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("VmRSS:") {
+            rss = value.trim().trim_end_matches("kB").trim().parse().ok();
+        }
+    }
+
+    Some(Process {
+        pid,
+        name: name?,
+        rss: rss?,
+    })
+}
+
+/// Processes using the most memory according to `/proc/*/status`.
+fn top_consumers() -> Vec<Process> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    let mut processes: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let status = fs::read_to_string(entry.path().join("status")).ok()?;
+            process(&status, pid)
+        })
+        .collect();
+
+    processes.sort_by_key(|process| Reverse(process.rss));
+    processes.truncate(TOP_CONSUMERS);
+    processes
+}
+
+/// Opens a PSI trigger firing once `threshold` percent of the window is stalled.
+fn trigger(resource: Resource, threshold: f64) -> std::io::Result<File> {
+    let stall = (TRIGGER_WINDOW_US as f64 * threshold / 100.0) as u64;
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(resource.file())?;
+    file.write_all(format!("some {stall} {TRIGGER_WINDOW_US}\0").as_bytes())?;
+    Ok(file)
+}
+
+/// Process name from a kernel OOM killer message, e.g.
+/// "Out of memory: Killed process 4321 (firefox) total-vm:...".
+fn oom_victim(message: &str) -> Option<String> {
+    let (_, rest) = message.split_once("Killed process ")?;
+    let (_, rest) = rest.split_once('(')?;
+    let (name, _) = rest.split_once(')')?;
+    Some(name.to_string())
+}
+
+/// Skips the `priority,sequence,timestamp,flags;` prefix of a `/dev/kmsg`
+/// record.
+fn kmsg_oom_victim(record: &str) -> Option<String> {
+    let (_, message) = record.split_once(';')?;
+    oom_victim(message)
+}
+
+fn handle_pressure(event_sender: &mpsc::Sender<Event>, pressure: Pressure) {
+    _ = event_sender.send(Event::Pressure(pressure));
+}
+
+fn handle_oom_kill(event_sender: &mpsc::Sender<Event>, victim: String) {
+    _ = event_sender.send(Event::OomKill(victim));
+}
+
+#[proxy(
+    interface = "org.freedesktop.oom1.Manager",
+    default_service = "org.freedesktop.oom1",
+    default_path = "/org/freedesktop/oom1"
+)]
+trait Oomd {
+    #[zbus(signal)]
+    fn killed(&self, cgroup: String, reason: String) -> zbus::Result<()>;
+}
+
+/// Reports the pressure of one resource, at most once per cooldown.
+struct Reporter {
+    resource: Resource,
+    threshold: f64,
+    cooldown: Duration,
+    last: Option<Instant>,
+    event_sender: mpsc::Sender<Event>,
+}
+
+impl Reporter {
+    /// A fired trigger is trusted even if the slower avg10 hasn't caught up yet.
+    fn check(&mut self, triggered: bool) {
+        let Some(avg10) = avg10(self.resource) else {
+            return;
+        };
+        if !triggered && avg10 < self.threshold {
+            return;
+        }
+        if self.last.is_some_and(|last| last.elapsed() < self.cooldown) {
+            return;
+        }
+        self.last = Some(Instant::now());
+
+        let top = match self.resource {
+            Resource::Memory => top_consumers(),
+            _ => Vec::new(),
+        };
+        handle_pressure(
+            &self.event_sender,
+            Pressure {
+                resource: self.resource,
+                avg10: avg10.round() as u32,
+                top,
+            },
+        );
+    }
+
+    /// Waits for a PSI trigger, returns when the kernel doesn't support them.
+    fn wait_for_trigger(&mut self) -> std::io::Result<()> {
+        let file = trigger(self.resource, self.threshold)?;
+
+        loop {
+            let mut fds = [PollFd::new(&file, PollFlags::PRI)];
+            match poll(&mut fds, None) {
+                Ok(_) => {}
+                Err(rustix::io::Errno::INTR) => continue,
+                Err(e) => return Err(e.into()),
+            }
+            if fds[0].revents().contains(PollFlags::ERR) {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.check(true);
+        }
+    }
+}
+
+pub struct PressureMonitor {
+    config: PressureConfig,
+}
+
+impl PressureMonitor {
+    pub fn new(config: PressureConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) {
+        let thresholds = [
+            (Resource::Memory, self.config.memory_percent),
+            (Resource::Cpu, self.config.cpu_percent),
+            (Resource::Io, self.config.io_percent),
+        ];
+
+        for (resource, threshold) in thresholds {
+            let Some(threshold) = threshold else {
+                continue;
+            };
+            if avg10(resource).is_none() {
+                eprintln!("PSI unavailable for {resource:?}");
+                continue;
+            }
+            let mut reporter = Reporter {
+                resource,
+                threshold,
+                cooldown: Duration::from_secs(self.config.cooldown_secs),
+                last: None,
+                event_sender: event_sender.clone(),
+            };
+            let interval = Duration::from_secs(self.config.interval_secs.max(1));
+
+            // Triggers block in poll(2), so they get a thread of their own.
+            std::thread::spawn(move || {
+                if let Err(e) = reporter.wait_for_trigger() {
+                    eprintln!("PSI trigger for {resource:?} unavailable, polling: {e}");
+                }
+
+                loop {
+                    reporter.check(false);
+                    std::thread::sleep(interval);
+                }
+            });
+        }
+
+        if self.config.oom_kills {
+            watch_kernel_log(event_sender);
+        }
+    }
+}
+
+/// Follows `/dev/kmsg` for OOM killer messages, quietly gives up if the kernel
+/// log isn't readable.
+fn watch_kernel_log(event_sender: mpsc::Sender<Event>) {
+    let Ok(mut kmsg) = File::open("/dev/kmsg") else {
+        return;
+    };
+    // Only new messages are interesting.
+    if kmsg.seek(SeekFrom::End(0)).is_err() {
+        return;
+    }
+
+    std::thread::spawn(move || {
+        // Every read returns exactly one record.
+        let mut buffer = [0; 8192];
+        loop {
+            let length = match kmsg.read(&mut buffer) {
+                Ok(length) => length,
+                // Records were overwritten before we got to them.
+                Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => continue,
+                Err(_) => return,
+            };
+
+            let record = String::from_utf8_lossy(&buffer[..length]);
+            let Some(victim) = kmsg_oom_victim(&record) else {
+                continue;
+            };
+
+            handle_oom_kill(&event_sender, victim);
+        }
+    });
+}
+
+pub struct OomdManager {
+    connection: zbus::Connection,
+}
+
+impl OomdManager {
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+
+        Ok(Self { connection })
+    }
+
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let oomd = OomdProxy::new(&self.connection).await?;
+        let mut killed_stream = oomd.receive_killed().await?;
+
+        tokio::spawn(async move {
+            while let Some(signal) = killed_stream.next().await {
+                let Ok(args) = signal.args() else {
+                    continue;
+                };
+
+                // Name the unit, e.g. "app-firefox-1234.scope".
+                let unit = args.cgroup.rsplit('/').next().unwrap_or(&args.cgroup);
+                handle_oom_kill(&event_sender, unit.to_string());
+            }
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `/proc/pressure/memory` under load.
+    const MEMORY: &str = "some avg10=23.57 avg60=8.12 avg300=1.89 total=48215733\n\
+                          full avg10=11.02 avg60=3.40 avg300=0.77 total=21877421\n";
+
+    /// `/proc/pressure/cpu`, which only has a meaningful `some` line.
+    const CPU: &str = "some avg10=0.00 avg60=0.12 avg300=0.30 total=112598120\n\
+                       full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n";
+
+    /// `/dev/kmsg` records around an OOM kill.
+    const OOM_KILL: &str = "3,1873,5823143882,-;Out of memory: Killed process 48213 (firefox) \
+                            total-vm:12817420kB, anon-rss:6410280kB, file-rss:0kB, \
+                            shmem-rss:1644kB, UID:1000 pgtables:16548kB oom_score_adj:100\n";
+    const OOM_REAPED: &str = "6,1874,5823170041,-;oom_reaper: reaped process 48213 (firefox), \
+                              now anon-rss:0kB, file-rss:0kB, shmem-rss:1644kB\n";
+
+    /// `/proc/<pid>/status`, shortened.
+    const STATUS: &str = "Name:\tfirefox\n\
+                          Umask:\t0022\n\
+                          State:\tS (sleeping)\n\
+                          Tgid:\t48213\n\
+                          Pid:\t48213\n\
+                          VmPeak:\t12817420 kB\n\
+                          VmRSS:\t 6412068 kB\n\
+                          Threads:\t142\n";
+
+    /// `/proc/<pid>/status` of a kernel thread.
+    const KTHREAD_STATUS: &str = "Name:\tkworker/3:1-events\n\
+                                  Umask:\t0000\n\
+                                  State:\tI (idle)\n\
+                                  Threads:\t1\n";
+
+    #[test]
+    fn parses_avg10() {
+        assert_eq!(parse_avg10(MEMORY), Some(23.57));
+        assert_eq!(parse_avg10(CPU), Some(0.0));
+        assert_eq!(parse_avg10(""), None);
+    }
+
+    #[test]
+    fn finds_oom_victims() {
+        assert_eq!(kmsg_oom_victim(OOM_KILL), Some("firefox".into()));
+        assert_eq!(kmsg_oom_victim(OOM_REAPED), None);
+        assert_eq!(
+            oom_victim("Memory cgroup out of memory: Killed process 7 (Web Content) total-vm:1kB"),
+            Some("Web Content".into())
+        );
+    }
+
+    #[test]
+    fn parses_process_status() {
+        assert_eq!(
+            process(STATUS, 48213),
+            Some(Process {
+                pid: 48213,
+                name: "firefox".into(),
+                rss: 6412068,
+            })
+        );
+        assert_eq!(process(KTHREAD_STATUS, 112), None);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::Event;
+use crate::config::PrivacyConfig;
+use serde::Serialize;
+use std::{fs, path::Path, sync::mpsc, time::Duration};
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingDevice {
+    Microphone,
+    Camera,
+}
+
+impl RecordingDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordingDevice::Microphone => "Microphone",
+            RecordingDevice::Camera => "Camera",
+        }
+    }
+
+    pub fn icon(&self) -> &'static str {
+        match self {
+            RecordingDevice::Microphone => "audio-input-microphone-symbolic",
+            RecordingDevice::Camera => "camera-web-symbolic",
+        }
+    }
+}
+
+/// Whether the process has a `/dev/video*` node open.
+fn uses_camera(process: &Path) -> bool {
+    let Ok(fds) = fs::read_dir(process.join("fd")) else {
+        return false;
+    };
+
+    fds.flatten()
+        .any(|fd| fs::read_link(fd.path()).is_ok_and(|target| target.starts_with("/dev/video")))
+}
+
+/// Names of the processes using a camera, only the user's own processes can
+/// be inspected.
+fn camera_users() -> Vec<String> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return Vec::new();
+    };
+
+    let mut users = Vec::new();
+    for entry in entries.flatten() {
+        let is_process = entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| name.chars().all(|c| c.is_ascii_digit()));
+        if !is_process || !uses_camera(&entry.path()) {
+            continue;
+        }
+
+        let Ok(comm) = fs::read_to_string(entry.path().join("comm")) else {
+            continue;
+        };
+        let name = comm.trim().to_string();
+        if !users.contains(&name) {
+            users.push(name);
+        }
+    }
+    users.sort();
+    users
+}
+
+fn handle_recording(event_sender: &mpsc::Sender<Event>, users: Vec<String>) -> bool {
+    event_sender
+        .send(Event::Recording(RecordingDevice::Camera, users))
+        .is_ok()
+}
+
+pub struct CameraMonitor {
+    config: PrivacyConfig,
+}
+
+impl CameraMonitor {
+    pub fn new(config: PrivacyConfig) -> Self {
+        Self { config }
+    }
+
+    /// Scans `/proc/*/fd`, sending the processes using a camera whenever they change.
+    pub fn subscribe(self, event_sender: mpsc::Sender<Event>) {
+        if !self.config.camera {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut users = Vec::new();
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+
+            loop {
+                interval.tick().await;
+
+                let current = tokio::task::spawn_blocking(camera_users)
+                    .await
+                    .unwrap_or_default();
+                if current != users {
+                    users = current;
+                    if !handle_recording(&event_sender, users.clone()) {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
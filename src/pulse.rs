@@ -1,14 +1,26 @@
+use crate::Event;
+use crate::audio::{AudioBackend, AudioRoutes, CardProfile, SinkPort, Volume};
 use libpulse_binding as pulse;
 use libpulse_binding::callbacks::ListResult;
 use libpulse_binding::context::subscribe::{Facility, InterestMaskSet};
 use libpulse_binding::proplist::Proplist;
+use libpulse_binding::volume::ChannelVolumes;
 use std::cell::RefCell;
+use std::collections::HashSet;
 use std::rc::Rc;
+use std::sync::mpsc;
 
-#[derive(PartialEq)]
-pub struct Volume {
-    pub value: u32,
-    pub muted: bool,
+fn volume(volume: &ChannelVolumes, muted: bool) -> Volume {
+    Volume {
+        value: volume
+            .max()
+            .print()
+            .trim_end_matches('%')
+            .trim()
+            .parse()
+            .unwrap_or_default(),
+        muted,
+    }
 }
 
 pub struct PulseManager {
@@ -63,6 +75,50 @@ impl PulseManager {
             .ok_or_else(|| anyhow::anyhow!("No default sink"))
     }
 
+    pub fn get_default_source_volume(&mut self) -> anyhow::Result<Volume> {
+        let source_name = self.get_default_source_name()?;
+        self.get_source_volume(&source_name)
+    }
+
+    pub fn get_default_source_name(&mut self) -> anyhow::Result<Box<str>> {
+        let result = Rc::new(RefCell::new(None));
+
+        let op = self.context.introspect().get_server_info({
+            let result = Rc::clone(&result);
+            move |info| {
+                *result.borrow_mut() = info.default_source_name.as_ref().map(|n| n.as_ref().into());
+            }
+        });
+
+        self.wait_for_operation(op)?;
+        result
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("No default source"))
+    }
+
+    pub fn get_source_volume(&mut self, source_name: &str) -> anyhow::Result<Volume> {
+        let result = Rc::new(RefCell::new(None));
+
+        let op = self
+            .context
+            .introspect()
+            .get_source_info_by_name(source_name, {
+                let result = Rc::clone(&result);
+                move |source_list| {
+                    if let ListResult::Item(item) = source_list {
+                        *result.borrow_mut() = Some(volume(&item.volume, item.mute));
+                    }
+                }
+            });
+
+        self.wait_for_operation(op)?;
+        result
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Source not found"))
+    }
+
     pub fn get_sink_volume(&mut self, sink_name: &str) -> anyhow::Result<Volume> {
         let result = Rc::new(RefCell::new(None));
 
@@ -70,17 +126,27 @@ impl PulseManager {
             let result = Rc::clone(&result);
             move |sink_list| {
                 if let ListResult::Item(item) = sink_list {
-                    *result.borrow_mut() = Some(Volume {
-                        value: item
-                            .volume
-                            .max()
-                            .print()
-                            .trim_end_matches('%')
-                            .trim()
-                            .parse()
-                            .unwrap_or_default(),
-                        muted: item.mute,
-                    });
+                    *result.borrow_mut() = Some(volume(&item.volume, item.mute));
+                }
+            }
+        });
+
+        self.wait_for_operation(op)?;
+        result
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("Sink not found"))
+    }
+
+    pub fn get_sink_description(&mut self, sink_name: &str) -> anyhow::Result<String> {
+        let result = Rc::new(RefCell::new(None));
+
+        let op = self.context.introspect().get_sink_info_by_name(sink_name, {
+            let result = Rc::clone(&result);
+            move |sink_list| {
+                if let ListResult::Item(item) = sink_list {
+                    *result.borrow_mut() =
+                        Some(item.description.as_deref().unwrap_or_default().into());
                 }
             }
         });
@@ -92,6 +158,100 @@ impl PulseManager {
             .ok_or_else(|| anyhow::anyhow!("Sink not found"))
     }
 
+    /// Names of the applications recording from a real source, monitors of
+    /// sinks such as peak meters don't count.
+    pub fn get_recording_applications(&mut self) -> anyhow::Result<Vec<String>> {
+        let monitors = Rc::new(RefCell::new(HashSet::new()));
+        let op = self.context.introspect().get_source_info_list({
+            let monitors = Rc::clone(&monitors);
+            move |source_list| {
+                if let ListResult::Item(item) = source_list
+                    && item.monitor_of_sink.is_some()
+                {
+                    monitors.borrow_mut().insert(item.index);
+                }
+            }
+        });
+        self.wait_for_operation(op)?;
+
+        let result = Rc::new(RefCell::new(Vec::new()));
+        let op = self.context.introspect().get_source_output_info_list({
+            let result = Rc::clone(&result);
+            move |output_list| {
+                let ListResult::Item(item) = output_list else {
+                    return;
+                };
+                if item.corked || monitors.borrow().contains(&item.source) {
+                    return;
+                }
+                let name = item
+                    .proplist
+                    .get_str(pulse::proplist::properties::APPLICATION_NAME)
+                    .unwrap_or_else(|| "an unknown application".into());
+                let mut result = result.borrow_mut();
+                if !result.contains(&name) {
+                    result.push(name);
+                }
+            }
+        });
+        self.wait_for_operation(op)?;
+
+        Ok(result.take())
+    }
+
+    pub fn get_audio_routes(&mut self) -> anyhow::Result<AudioRoutes> {
+        let routes = Rc::new(RefCell::new(AudioRoutes::default()));
+
+        let op = self.context.introspect().get_card_info_list({
+            let routes = Rc::clone(&routes);
+            move |card_list| {
+                let ListResult::Item(item) = card_list else {
+                    return;
+                };
+                let Some(profile) = &item.active_profile else {
+                    return;
+                };
+                let card = item
+                    .proplist
+                    .get_str(pulse::proplist::properties::DEVICE_DESCRIPTION)
+                    .or_else(|| item.name.as_ref().map(|name| name.to_string()))
+                    .unwrap_or_default();
+                routes.borrow_mut().profiles.insert(
+                    item.index,
+                    CardProfile {
+                        card,
+                        name: profile.name.as_deref().unwrap_or_default().into(),
+                        description: profile.description.as_deref().unwrap_or_default().into(),
+                    },
+                );
+            }
+        });
+        self.wait_for_operation(op)?;
+
+        let op = self.context.introspect().get_sink_info_list({
+            let routes = Rc::clone(&routes);
+            move |sink_list| {
+                let ListResult::Item(item) = sink_list else {
+                    return;
+                };
+                let Some(port) = &item.active_port else {
+                    return;
+                };
+                routes.borrow_mut().ports.insert(
+                    item.index,
+                    SinkPort {
+                        sink: item.description.as_deref().unwrap_or_default().into(),
+                        name: port.name.as_deref().unwrap_or_default().into(),
+                        description: port.description.as_deref().unwrap_or_default().into(),
+                    },
+                );
+            }
+        });
+        self.wait_for_operation(op)?;
+
+        Ok(routes.take())
+    }
+
     pub fn wait_for_operation<T>(
         &mut self,
         op: pulse::operation::Operation<T>,
@@ -117,3 +277,53 @@ impl PulseManager {
             .set_subscribe_callback(Some(Box::new(callback)));
     }
 }
+
+impl AudioBackend for PulseManager {
+    fn default_sink_volume(&mut self) -> anyhow::Result<Volume> {
+        self.get_default_sink_volume()
+    }
+
+    fn default_source_volume(&mut self) -> anyhow::Result<Volume> {
+        self.get_default_source_volume()
+    }
+
+    fn default_sink_description(&mut self) -> anyhow::Result<String> {
+        let sink_name = self.get_default_sink_name()?;
+        self.get_sink_description(&sink_name)
+    }
+
+    fn recording_applications(&mut self) -> anyhow::Result<Vec<String>> {
+        self.get_recording_applications()
+    }
+
+    fn audio_routes(&mut self) -> anyhow::Result<AudioRoutes> {
+        self.get_audio_routes()
+    }
+
+    fn subscribe(&mut self, event_sender: mpsc::Sender<Event>, recordings: bool) {
+        self.set_subscription_callback(move |facility, _, _| {
+            let events: &[Event] = match facility {
+                // Sink changes include switching the active port.
+                Some(Facility::Sink) => &[Event::VolumeChanged, Event::AudioRoutesChanged],
+                Some(Facility::Source) => &[Event::SourceVolumeChanged],
+                Some(Facility::Card) => &[Event::AudioRoutesChanged],
+                Some(Facility::SourceOutput) if recordings => &[Event::SourceOutputsChanged],
+                _ => &[],
+            };
+
+            for event in events {
+                _ = event_sender.send(event.clone());
+            }
+        });
+
+        PulseManager::subscribe(
+            self,
+            InterestMaskSet::SERVER
+                | InterestMaskSet::SINK_INPUT
+                | InterestMaskSet::SINK
+                | InterestMaskSet::SOURCE
+                | InterestMaskSet::CARD
+                | InterestMaskSet::SOURCE_OUTPUT,
+        );
+    }
+}
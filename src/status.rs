@@ -0,0 +1,443 @@
+use crate::Event;
+use crate::audio::Volume;
+use crate::config::StatusConfig;
+use crate::disk::DiskLevel;
+use crate::hotplug::{DeviceKind, HotplugChange};
+use crate::leds::LockKey;
+use crate::network::Connectivity;
+use crate::pressure::Resource;
+use crate::privacy::RecordingDevice;
+use crate::systemd::UnitScope;
+use crate::udisks::StorageChange;
+use crate::upower::BatterySnapshot;
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Clients that don't keep up are dropped rather than stalling notifications.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// What status bars show, sent along with every event.
+#[derive(PartialEq, Serialize, Debug)]
+pub struct Status {
+    /// Default sink volume in percent, `None` without a default sink.
+    pub volume: Option<u32>,
+    pub muted: bool,
+    /// Description of the default sink, e.g. "Built-in Audio Analog Stereo".
+    pub sink: Option<String>,
+    pub battery: BatteryStatus,
+}
+
+#[derive(PartialEq, Serialize, Debug)]
+pub struct BatteryStatus {
+    pub percentage: u64,
+    pub state: String,
+    pub level: String,
+    pub on_battery: bool,
+}
+
+impl Status {
+    pub fn new(volume: Option<&Volume>, sink: Option<&str>, battery: &BatterySnapshot) -> Self {
+        Self {
+            volume: volume.map(|volume| volume.value),
+            muted: volume.is_some_and(|volume| volume.muted),
+            sink: sink.map(String::from),
+            battery: BatteryStatus {
+                percentage: battery.percentage,
+                state: battery.state.to_string(),
+                level: battery.level.to_string(),
+                on_battery: battery.on_battery,
+            },
+        }
+    }
+}
+
+/// The events status bars see, decoupled from [`Event`] so that the protocol
+/// doesn't change along with the daemon's internals. Anything the state
+/// already carries, like battery changes, goes without data.
+#[derive(PartialEq, Serialize, Debug)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum StatusEvent<'a> {
+    VolumeChanged,
+    SourceVolumeChanged,
+    AudioRoutesChanged,
+    BatteryChanged,
+    PowerProfile {
+        profile: &'a str,
+    },
+    PerformanceDegraded {
+        reason: &'a str,
+    },
+    Suspend,
+    Resume,
+    Shutdown,
+    Lid {
+        closed: bool,
+    },
+    Network {
+        /// SSID or connection name, `None` when disconnected.
+        connection: Option<&'a str>,
+    },
+    Connectivity {
+        state: &'static str,
+    },
+    Vpn {
+        name: &'a str,
+        connected: bool,
+    },
+    Bluetooth {
+        alias: &'a str,
+        connected: bool,
+        battery: Option<u8>,
+    },
+    Storage {
+        label: &'a str,
+        device: &'a str,
+        mount_point: Option<&'a str>,
+        change: StorageChange,
+    },
+    DiskSpace {
+        path: &'a str,
+        level: DiskLevel,
+        free: u64,
+        total: u64,
+    },
+    Overheating {
+        sensor: &'a str,
+        celsius: f64,
+    },
+    FanFailure {
+        fan: &'a str,
+    },
+    Pressure {
+        resource: Resource,
+        avg10: u32,
+    },
+    OomKill {
+        process: &'a str,
+    },
+    UnitFailed {
+        unit: &'a str,
+        scope: UnitScope,
+        result: &'a str,
+    },
+    LockKey {
+        key: LockKey,
+        on: bool,
+    },
+    NowPlaying {
+        player: &'a str,
+        title: &'a str,
+        artist: &'a str,
+        playing: bool,
+    },
+    PlayerStopped {
+        player: &'a str,
+    },
+    Hotplug {
+        kind: DeviceKind,
+        name: &'a str,
+        change: HotplugChange,
+    },
+    Recording {
+        device: RecordingDevice,
+        applications: &'a [String],
+    },
+    Brightness {
+        percentage: u32,
+    },
+}
+
+impl<'a> StatusEvent<'a> {
+    /// `None` for events internal to the daemon, like notification actions
+    /// or timer ticks.
+    pub fn new(event: &'a Event) -> Option<Self> {
+        Some(match event {
+            Event::VolumeChanged => Self::VolumeChanged,
+            Event::SourceVolumeChanged => Self::SourceVolumeChanged,
+            Event::AudioRoutesChanged => Self::AudioRoutesChanged,
+            Event::BatteryState(_)
+            | Event::BatteryLevel(_)
+            | Event::OnBattery(_)
+            | Event::BatteryPercentage(_) => Self::BatteryChanged,
+            Event::PowerProfile(profile) => Self::PowerProfile { profile },
+            Event::PerformanceDegraded(reason) => Self::PerformanceDegraded { reason },
+            Event::PrepareForSleep(true) => Self::Suspend,
+            Event::PrepareForSleep(false) => Self::Resume,
+            Event::PrepareForShutdown(true) => Self::Shutdown,
+            Event::LidClosed(closed) => Self::Lid { closed: *closed },
+            Event::PrimaryConnection(network) => Self::Network {
+                connection: network
+                    .as_ref()
+                    .map(|network| network.ssid.as_deref().unwrap_or(&network.id)),
+            },
+            Event::Connectivity(connectivity) => Self::Connectivity {
+                state: match connectivity {
+                    Connectivity::Unknown => "unknown",
+                    Connectivity::None => "none",
+                    Connectivity::Portal => "portal",
+                    Connectivity::Limited => "limited",
+                    Connectivity::Full => "full",
+                },
+            },
+            Event::Vpn(name, connected) => Self::Vpn {
+                name,
+                connected: *connected,
+            },
+            Event::BluetoothConnected(device) | Event::BluetoothBattery(device) => {
+                Self::Bluetooth {
+                    alias: &device.alias,
+                    connected: device.connected,
+                    battery: device.battery,
+                }
+            }
+            Event::Storage(device, change) => Self::Storage {
+                label: &device.label,
+                device: &device.device,
+                mount_point: device.mount_point.as_deref(),
+                change: *change,
+            },
+            Event::DiskSpace(space) => Self::DiskSpace {
+                path: &space.path,
+                level: space.level,
+                free: space.free,
+                total: space.total,
+            },
+            Event::Overheating(sensor) => Self::Overheating {
+                sensor: &sensor.name,
+                celsius: sensor.celsius(),
+            },
+            Event::FanFailure(fan) => Self::FanFailure { fan },
+            Event::Pressure(pressure) => Self::Pressure {
+                resource: pressure.resource,
+                avg10: pressure.avg10,
+            },
+            Event::OomKill(process) => Self::OomKill { process },
+            Event::UnitFailed(unit) => Self::UnitFailed {
+                unit: &unit.name,
+                scope: unit.scope,
+                result: &unit.result,
+            },
+            Event::LockKey(key, on) => Self::LockKey { key: *key, on: *on },
+            Event::NowPlaying(track) => Self::NowPlaying {
+                player: &track.player,
+                title: &track.title,
+                artist: &track.artist,
+                playing: track.playing,
+            },
+            Event::PlayerStopped(player) => Self::PlayerStopped { player },
+            Event::Hotplug(device, change) => Self::Hotplug {
+                kind: device.kind,
+                name: &device.name,
+                change: *change,
+            },
+            Event::Recording(device, applications) => Self::Recording {
+                device: *device,
+                applications,
+            },
+            Event::Brightness(percentage) => Self::Brightness {
+                percentage: *percentage,
+            },
+            Event::ActionInvoked(..)
+            | Event::NotificationClosed(_)
+            | Event::BatteryHealth
+            | Event::PrepareForShutdown(false)
+            | Event::CriticalCountdown
+            | Event::DriveRemoved(_)
+            | Event::SourceOutputsChanged => return None,
+        })
+    }
+}
+
+/// `$XDG_RUNTIME_DIR/sysnotifier.sock`
+fn socket_path() -> anyhow::Result<PathBuf> {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .ok_or_else(|| anyhow::anyhow!("XDG_RUNTIME_DIR is not set"))?;
+
+    Ok(PathBuf::from(runtime_dir).join("sysnotifier.sock"))
+}
+
+/// One JSON line, `event` and `data` are left out for the initial state.
+fn line(event: Option<&StatusEvent>, status: &Status) -> anyhow::Result<String> {
+    let mut line = match event {
+        Some(event) => serde_json::to_value(event)?,
+        None => serde_json::Value::Null,
+    };
+    line["state"] = serde_json::to_value(status)?;
+
+    Ok(format!("{line}\n"))
+}
+
+#[derive(Default)]
+struct Clients {
+    streams: Vec<UnixStream>,
+    /// Replayed to new clients so they don't wait for the next event.
+    last: Option<String>,
+}
+
+/// Events and state as JSON lines on stdout and a Unix socket, for status bars.
+pub struct StatusStream {
+    stdout: bool,
+    socket: bool,
+    clients: Arc<Mutex<Clients>>,
+}
+
+impl StatusStream {
+    pub fn new(config: &StatusConfig) -> anyhow::Result<Self> {
+        let clients = Arc::new(Mutex::new(Clients::default()));
+        if config.socket {
+            Self::listen(Arc::clone(&clients))?;
+        }
+
+        Ok(Self {
+            stdout: config.stdout,
+            socket: config.socket,
+            clients,
+        })
+    }
+
+    fn listen(clients: Arc<Mutex<Clients>>) -> anyhow::Result<()> {
+        let path = socket_path()?;
+        // A socket nobody listens on was left behind by an instance that
+        // didn't exit cleanly.
+        match UnixStream::connect(&path) {
+            Ok(_) => anyhow::bail!("Another instance is listening on {}", path.display()),
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => fs::remove_file(&path)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let listener = UnixListener::bind(&path)?;
+
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                let mut clients = clients.lock().unwrap();
+                if let Some(last) = &clients.last
+                    && stream.write_all(last.as_bytes()).is_err()
+                {
+                    continue;
+                }
+                clients.streams.push(stream);
+            }
+        });
+
+        Ok(())
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.stdout || self.socket
+    }
+
+    /// Writes `status` after `event`, or on its own on startup. Stdout is
+    /// given up on once its reader goes away, e.g. a restarted status bar.
+    pub fn send(&mut self, event: Option<&StatusEvent>, status: &Status) -> anyhow::Result<()> {
+        let line = line(event, status)?;
+
+        if self.stdout {
+            let mut stdout = std::io::stdout().lock();
+            if let Err(e) = stdout
+                .write_all(line.as_bytes())
+                .and_then(|_| stdout.flush())
+            {
+                eprintln!("Failed to write status to stdout, no longer writing it: {e}");
+                self.stdout = false;
+            }
+        }
+
+        let mut clients = self.clients.lock().unwrap();
+        clients
+            .streams
+            .retain_mut(|stream| stream.write_all(line.as_bytes()).is_ok());
+        clients.last = Some(line);
+
+        Ok(())
+    }
+}
+
+/// Copies the stream of a running instance to stdout, for status bars that
+/// run a command rather than connect to a socket.
+pub fn print_stream() -> anyhow::Result<()> {
+    let path = socket_path()?;
+    let stream = UnixStream::connect(&path).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to connect to {}, is `[status] socket` enabled? {e}",
+            path.display()
+        )
+    })?;
+
+    std::io::copy(&mut &stream, &mut std::io::stdout().lock())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::upower::{BatteryLevel, BatteryState};
+
+    fn status() -> Status {
+        let battery = BatterySnapshot {
+            percentage: 76,
+            state: BatteryState::Discharging,
+            level: BatteryLevel::None,
+            on_battery: true,
+            ..BatterySnapshot::default()
+        };
+
+        Status::new(
+            Some(&Volume {
+                value: 40,
+                muted: true,
+            }),
+            Some("Built-in Audio Analog Stereo"),
+            &battery,
+        )
+    }
+
+    #[test]
+    fn lines_carry_event_and_state() -> anyhow::Result<()> {
+        let state = r#""state":{"battery":{"level":"none","on_battery":true,"percentage":76,"state":"discharging"},"muted":true,"sink":"Built-in Audio Analog Stereo","volume":40}"#;
+
+        assert_eq!(line(None, &status())?, format!("{{{state}}}\n"));
+        assert_eq!(
+            line(Some(&StatusEvent::VolumeChanged), &status())?,
+            format!("{{\"event\":\"volume_changed\",{state}}}\n")
+        );
+        let vpn = Event::Vpn("work".into(), true);
+        assert_eq!(
+            line(StatusEvent::new(&vpn).as_ref(), &status())?,
+            format!(
+                "{{\"data\":{{\"connected\":true,\"name\":\"work\"}},\"event\":\"vpn\",{state}}}\n"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn internal_events_are_not_published() {
+        assert_eq!(
+            StatusEvent::new(&Event::BatteryPercentage(75)),
+            Some(StatusEvent::BatteryChanged)
+        );
+        assert_eq!(StatusEvent::new(&Event::CriticalCountdown), None);
+        assert_eq!(StatusEvent::new(&Event::NotificationClosed(3)), None);
+        assert_eq!(
+            StatusEvent::new(&Event::ActionInvoked(3, "storage-open".into())),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_sink_has_no_volume() {
+        let status = Status::new(None, None, &BatterySnapshot::default());
+
+        assert_eq!(status.volume, None);
+        assert!(!status.muted);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use serde::Serialize;
+use std::{collections::HashMap, sync::mpsc};
+use zbus::{
+    MatchRule, MessageStream,
+    fdo::PropertiesProxy,
+    message,
+    names::InterfaceName,
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
+
+const SYSTEMD: &str = "org.freedesktop.systemd1";
+const UNIT_INTERFACE: &str = "org.freedesktop.systemd1.Unit";
+
+/// Which systemd instance a unit belongs to.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnitScope {
+    User,
+    System,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct FailedUnit {
+    pub scope: UnitScope,
+    pub name: String,
+    /// Why the unit failed, e.g. `exit-code` or `timeout`.
+    pub result: String,
+    /// Exit status of the main process, for services that exited on their own.
+    pub exit_status: Option<i32>,
+}
+
+type UnitInfo = (
+    String,
+    String,
+    String,
+    String,
+    String,
+    String,
+    OwnedObjectPath,
+    u32,
+    String,
+    OwnedObjectPath,
+);
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Manager",
+    default_service = "org.freedesktop.systemd1",
+    default_path = "/org/freedesktop/systemd1"
+)]
+trait Manager {
+    /// Asks systemd to emit signals, they're suppressed without subscribers.
+    fn subscribe(&self) -> zbus::Result<()>;
+
+    fn list_units(&self) -> zbus::Result<Vec<UnitInfo>>;
+
+    #[zbus(allow_interactive_auth)]
+    fn restart_unit(&self, name: &str, mode: &str) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(signal)]
+    fn unit_new(&self, id: String, unit: OwnedObjectPath) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    fn unit_removed(&self, id: String, unit: OwnedObjectPath) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.systemd1.Unit",
+    default_service = "org.freedesktop.systemd1"
+)]
+trait Unit {
+    #[zbus(property)]
+    fn id(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn active_state(&self) -> zbus::Result<String>;
+}
+
+/// The type specific interface of a unit, e.g. `org.freedesktop.systemd1.Service`.
+fn type_interface(name: &str) -> Option<String> {
+    let (_, kind) = name.rsplit_once('.')?;
+    let mut chars = kind.chars();
+    let first = chars.next()?.to_ascii_uppercase();
+    Some(format!("{SYSTEMD}.{first}{}", chars.as_str()))
+}
+
+async fn property<T: TryFrom<OwnedValue>>(
+    properties: &PropertiesProxy<'_>,
+    interface: &str,
+    name: &str,
+) -> Option<T> {
+    let interface = InterfaceName::try_from(interface).ok()?;
+    let value = properties.get(interface, name).await.ok()?;
+    T::try_from(value).ok()
+}
+
+async fn describe(
+    connection: &zbus::Connection,
+    scope: UnitScope,
+    path: OwnedObjectPath,
+) -> Option<FailedUnit> {
+    let unit = UnitProxy::builder(connection)
+        .path(path.clone())
+        .ok()?
+        .build()
+        .await
+        .ok()?;
+    let name = unit.id().await.ok()?;
+
+    let properties = PropertiesProxy::builder(connection)
+        .destination(SYSTEMD)
+        .ok()?
+        .path(path)
+        .ok()?
+        .build()
+        .await
+        .ok()?;
+    let interface = type_interface(&name)?;
+    let result = property(&properties, &interface, "Result")
+        .await
+        .unwrap_or_default();
+
+    // CLD_EXITED, the status is a signal number otherwise.
+    let exited = property::<i32>(&properties, &interface, "ExecMainCode").await == Some(1);
+    let exit_status = match exited {
+        true => property(&properties, &interface, "ExecMainStatus").await,
+        false => None,
+    };
+
+    Some(FailedUnit {
+        scope,
+        name,
+        result,
+        exit_status,
+    })
+}
+
+fn handle_unit_failed(event_sender: &mpsc::Sender<Event>, unit: FailedUnit) {
+    _ = event_sender.send(Event::UnitFailed(unit));
+}
+
+pub struct SystemdManager {
+    connection: zbus::Connection,
+    scope: UnitScope,
+}
+
+impl SystemdManager {
+    pub async fn new(scope: UnitScope) -> anyhow::Result<Self> {
+        let connection = match scope {
+            UnitScope::User => zbus::Connection::session().await?,
+            UnitScope::System => zbus::Connection::system().await?,
+        };
+
+        // Also fails early when there's no systemd instance on the bus.
+        ManagerProxy::new(&connection).await?.subscribe().await?;
+
+        Ok(Self { connection, scope })
+    }
+
+    pub fn scope(&self) -> UnitScope {
+        self.scope
+    }
+
+    pub async fn restart(&self, unit: &str) -> anyhow::Result<()> {
+        let manager = ManagerProxy::new(&self.connection).await?;
+        manager.restart_unit(unit, "replace").await?;
+        Ok(())
+    }
+
+    /// Sends an event whenever a unit enters the failed state.
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let manager = ManagerProxy::new(&self.connection).await?;
+
+        // A single match for all units instead of a proxy per unit.
+        let rule = MatchRule::builder()
+            .msg_type(message::Type::Signal)
+            .sender(SYSTEMD)?
+            .interface("org.freedesktop.DBus.Properties")?
+            .member("PropertiesChanged")?
+            .add_arg(UNIT_INTERFACE)?
+            .build();
+        let mut changed_stream =
+            MessageStream::for_match_rule(rule, &self.connection, None).await?;
+        let mut new_stream = manager.receive_unit_new().await?;
+        let mut removed_stream = manager.receive_unit_removed().await?;
+
+        let mut states: HashMap<OwnedObjectPath, String> = manager
+            .list_units()
+            .await?
+            .into_iter()
+            .map(|unit| (unit.6, unit.3))
+            .collect();
+
+        let connection = self.connection.clone();
+        let scope = self.scope;
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    Some(Ok(message)) = changed_stream.next() => {
+                        let header = message.header();
+                        let Some(path) = header.path() else {
+                            continue;
+                        };
+                        let path = OwnedObjectPath::from(path.to_owned());
+                        let Ok((_, changed, _)) = message
+                            .body()
+                            .deserialize::<(String, HashMap<String, OwnedValue>, Vec<String>)>()
+                        else {
+                            continue;
+                        };
+                        let Some(state) = changed
+                            .get("ActiveState")
+                            .and_then(|state| String::try_from(state.clone()).ok())
+                        else {
+                            continue;
+                        };
+
+                        // systemd repeats ActiveState with every other change.
+                        let previous = states.insert(path.clone(), state.clone());
+                        if state != "failed" || previous.as_deref() == Some("failed") {
+                            continue;
+                        }
+                        if let Some(unit) = describe(&connection, scope, path).await {
+                            handle_unit_failed(&event_sender, unit);
+                        }
+                    }
+                    Some(signal) = new_stream.next() => {
+                        if let Ok(args) = signal.args() {
+                            states.entry(args.unit.clone()).or_default();
+                        }
+                    }
+                    Some(signal) = removed_stream.next() => {
+                        if let Ok(args) = signal.args() {
+                            states.remove(&args.unit);
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::{HashMap, HashSet};
+use tokio::net::UnixStream;
+use zbus::{
+    interface,
+    object_server::{InterfaceRef, SignalEmitter},
+    zvariant::{OwnedValue, Value},
+};
+
+const NOTIFICATIONS_PATH: &str = "/org/freedesktop/Notifications";
+
+/// A `Notify` call as received by the fake server.
+#[derive(PartialEq, Clone, Default, Debug)]
+pub struct Notification {
+    /// ID the server answered with.
+    pub id: u32,
+    pub replaces_id: u32,
+    pub app_name: String,
+    pub icon: String,
+    pub summary: String,
+    pub body: String,
+    /// Action keys and labels, alternating.
+    pub actions: Vec<String>,
+    pub urgency: Option<u8>,
+    /// The `value` hint.
+    pub progress: Option<i32>,
+    /// The `image-path` hint.
+    pub image: Option<String>,
+    pub timeout: i32,
+}
+
+/// An in-process `org.freedesktop.Notifications` server recording every call.
+#[derive(Default)]
+pub struct FakeNotifications {
+    received: Vec<Notification>,
+    closed: Vec<u32>,
+    /// Notifications still on screen, replacing any other opens a new one.
+    open: HashSet<u32>,
+    last_id: u32,
+}
+
+fn hint<'a>(hints: &'a HashMap<String, OwnedValue>, key: &str) -> Option<&'a Value<'static>> {
+    hints.get(key).map(|value| &**value)
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl FakeNotifications {
+    #[allow(clippy::too_many_arguments)]
+    fn notify(
+        &mut self,
+        app_name: String,
+        replaces_id: u32,
+        app_icon: String,
+        summary: String,
+        body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        expire_timeout: i32,
+    ) -> u32 {
+        let id = match self.open.contains(&replaces_id) {
+            true => replaces_id,
+            false => {
+                self.last_id += 1;
+                self.last_id
+            }
+        };
+        self.open.insert(id);
+
+        self.received.push(Notification {
+            id,
+            replaces_id,
+            app_name,
+            icon: app_icon,
+            summary,
+            body,
+            actions,
+            urgency: match hint(&hints, "urgency") {
+                Some(Value::U8(urgency)) => Some(*urgency),
+                _ => None,
+            },
+            progress: match hint(&hints, "value") {
+                Some(Value::I32(value)) => Some(*value),
+                _ => None,
+            },
+            image: match hint(&hints, "image-path") {
+                Some(Value::Str(image)) => Some(image.to_string()),
+                _ => None,
+            },
+            timeout: expire_timeout,
+        });
+        id
+    }
+
+    async fn close_notification(
+        &mut self,
+        id: u32,
+        #[zbus(signal_emitter)] emitter: SignalEmitter<'_>,
+    ) -> zbus::fdo::Result<()> {
+        self.closed.push(id);
+        if self.open.remove(&id) {
+            // Closed by a call to CloseNotification.
+            Self::notification_closed(&emitter, id, 3).await?;
+        }
+        Ok(())
+    }
+
+    #[zbus(signal)]
+    async fn action_invoked(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        action_key: &str,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn notification_closed(
+        emitter: &SignalEmitter<'_>,
+        id: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+}
+
+/// The server side of a private connection serving [`FakeNotifications`].
+pub struct FakeServer {
+    connection: zbus::Connection,
+}
+
+impl FakeServer {
+    /// Starts a server, returning it with the client end of the connection.
+    pub async fn start() -> anyhow::Result<(Self, zbus::Connection)> {
+        let (server, client) = UnixStream::pair()?;
+        let guid = zbus::Guid::generate();
+
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(guid)?
+            .p2p()
+            .serve_at(NOTIFICATIONS_PATH, FakeNotifications::default())?
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+        let (server, client) = futures_lite::future::try_zip(server, client).await?;
+
+        Ok((Self { connection: server }, client))
+    }
+
+    async fn fake(&self) -> anyhow::Result<InterfaceRef<FakeNotifications>> {
+        Ok(self
+            .connection
+            .object_server()
+            .interface(NOTIFICATIONS_PATH)
+            .await?)
+    }
+
+    /// Notifications received since the last call.
+    pub async fn received(&self) -> anyhow::Result<Vec<Notification>> {
+        let fake = self.fake().await?;
+        Ok(std::mem::take(&mut fake.get_mut().await.received))
+    }
+
+    /// The only notification received since the last call.
+    pub async fn single(&self) -> anyhow::Result<Notification> {
+        let mut received = self.received().await?;
+        anyhow::ensure!(
+            received.len() == 1,
+            "expected one notification, got {received:?}"
+        );
+        Ok(received.remove(0))
+    }
+
+    /// IDs passed to `CloseNotification` since the last call.
+    pub async fn closed(&self) -> anyhow::Result<Vec<u32>> {
+        let fake = self.fake().await?;
+        Ok(std::mem::take(&mut fake.get_mut().await.closed))
+    }
+
+    /// Clicks `action_key` on notification `id`.
+    pub async fn invoke_action(&self, id: u32, action_key: &str) -> anyhow::Result<()> {
+        let fake = self.fake().await?;
+        FakeNotifications::action_invoked(fake.signal_emitter(), id, action_key).await?;
+        Ok(())
+    }
+
+    /// Closes notification `id` as if the user dismissed it.
+    pub async fn dismiss(&self, id: u32) -> anyhow::Result<()> {
+        let fake = self.fake().await?;
+        fake.get_mut().await.open.remove(&id);
+        FakeNotifications::notification_closed(fake.signal_emitter(), id, 2).await?;
+        Ok(())
+    }
+}
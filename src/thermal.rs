@@ -0,0 +1,368 @@
+use crate::Event;
+use crate::config::ThermalConfig;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// A temperature sensor, values are in millidegrees Celsius as exposed by sysfs.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct Sensor {
+    pub name: String,
+    pub temperature: i64,
+    pub crit: Option<i64>,
+}
+
+impl Sensor {
+    pub fn celsius(&self) -> f64 {
+        self.temperature as f64 / 1000.0
+    }
+
+    pub fn crit_celsius(&self) -> Option<f64> {
+        self.crit.map(|crit| crit as f64 / 1000.0)
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    Some(value.trim().to_string())
+}
+
+fn read_number(path: &Path) -> Option<i64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+/// Entries of a sysfs class directory whose name starts with `prefix`, sorted.
+fn entries(dir: &Path, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<_> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// `/sys/class/thermal/thermal_zone*`, with the critical trip point as `crit`.
+fn thermal_zones(sysfs: &Path) -> Vec<Sensor> {
+    entries(&sysfs.join("class/thermal"), "thermal_zone")
+        .into_iter()
+        .filter_map(|zone| {
+            let temperature = read_number(&zone.join("temp"))?;
+            let crit = (0..)
+                .map_while(|i| {
+                    let kind = read_trimmed(&zone.join(format!("trip_point_{i}_type")))?;
+                    Some((kind, zone.join(format!("trip_point_{i}_temp"))))
+                })
+                .find(|(kind, _)| kind == "critical")
+                .and_then(|(_, path)| read_number(&path));
+
+            Some(Sensor {
+                name: read_trimmed(&zone.join("type")).unwrap_or_else(|| file_name(&zone)),
+                temperature,
+                crit,
+            })
+        })
+        .collect()
+}
+
+/// Channel numbers of `<kind>N_input` files in a hwmon directory.
+fn channels(hwmon: &Path, kind: &str) -> Vec<String> {
+    entries(hwmon, kind)
+        .iter()
+        .filter_map(|path| {
+            file_name(path)
+                .strip_prefix(kind)?
+                .strip_suffix("_input")
+                .map(String::from)
+        })
+        .collect()
+}
+
+/// Name of a hwmon channel, e.g. "coretemp Package id 0".
+fn channel_name(hwmon: &Path, kind: &str, channel: &str) -> String {
+    let chip = read_trimmed(&hwmon.join("name")).unwrap_or_else(|| file_name(hwmon));
+    let label = read_trimmed(&hwmon.join(format!("{kind}{channel}_label")))
+        .unwrap_or_else(|| format!("{kind}{channel}"));
+    format!("{chip} {label}")
+}
+
+/// `/sys/class/hwmon/hwmon*/temp*_input`, with `temp*_crit` when present.
+fn hwmon_sensors(sysfs: &Path) -> Vec<Sensor> {
+    let mut sensors = Vec::new();
+    for hwmon in entries(&sysfs.join("class/hwmon"), "hwmon") {
+        for channel in channels(&hwmon, "temp") {
+            let Some(temperature) = read_number(&hwmon.join(format!("temp{channel}_input"))) else {
+                continue;
+            };
+
+            sensors.push(Sensor {
+                name: channel_name(&hwmon, "temp", &channel),
+                temperature,
+                crit: read_number(&hwmon.join(format!("temp{channel}_crit"))),
+            });
+        }
+    }
+    sensors
+}
+
+/// Fans flagged by the driver through `fan*_alarm` or `fan*_fault`.
+fn failed_fans(sysfs: &Path) -> Vec<String> {
+    let mut fans = Vec::new();
+    for hwmon in entries(&sysfs.join("class/hwmon"), "hwmon") {
+        for channel in channels(&hwmon, "fan") {
+            let failed = ["alarm", "fault"].iter().any(|flag| {
+                read_number(&hwmon.join(format!("fan{channel}_{flag}"))).is_some_and(|v| v != 0)
+            });
+            if failed {
+                fans.push(channel_name(&hwmon, "fan", &channel));
+            }
+        }
+    }
+    fans
+}
+
+pub fn sensors(sysfs: &Path) -> Vec<Sensor> {
+    let mut sensors = thermal_zones(sysfs);
+    sensors.extend(hwmon_sensors(sysfs));
+    sensors
+}
+
+/// Whether the sensor is above the configured threshold or close to its own
+/// critical temperature.
+fn overheating(config: &ThermalConfig, sensor: &Sensor) -> bool {
+    let celsius = sensor.celsius();
+
+    config
+        .threshold_celsius
+        .is_some_and(|threshold| celsius >= threshold)
+        || config
+            .crit_margin_celsius
+            .zip(sensor.crit_celsius())
+            .is_some_and(|(margin, crit)| crit > 0.0 && celsius >= crit - margin)
+}
+
+fn handle_overheating(event_sender: &mpsc::Sender<Event>, sensor: Sensor) {
+    _ = event_sender.send(Event::Overheating(sensor));
+}
+
+fn handle_fan_failure(event_sender: &mpsc::Sender<Event>, fan: String) {
+    _ = event_sender.send(Event::FanFailure(fan));
+}
+
+pub struct ThermalMonitor {
+    config: ThermalConfig,
+    sysfs: PathBuf,
+    /// When each sensor or fan was last warned about.
+    warned: HashMap<String, Instant>,
+}
+
+impl ThermalMonitor {
+    pub fn new(config: ThermalConfig) -> Self {
+        Self::with_sysfs(config, "/sys")
+    }
+
+    pub fn with_sysfs(config: ThermalConfig, sysfs: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            sysfs: sysfs.into(),
+            warned: HashMap::new(),
+        }
+    }
+
+    /// Whether `name` may be warned about again at `now`, starting its cooldown if so.
+    fn cooled_down(&mut self, name: &str, now: Instant) -> bool {
+        let cooldown = Duration::from_secs(self.config.cooldown_secs);
+        if self
+            .warned
+            .get(name)
+            .is_some_and(|warned| now.duration_since(*warned) < cooldown)
+        {
+            return false;
+        }
+
+        self.warned.insert(name.to_string(), now);
+        true
+    }
+
+    /// Reads all sensors once, returning the overheating ones and failed fans
+    /// that are due for a warning.
+    fn check(&mut self, now: Instant) -> (Vec<Sensor>, Vec<String>) {
+        let hot: Vec<_> = sensors(&self.sysfs)
+            .into_iter()
+            .filter(|sensor| overheating(&self.config, sensor))
+            .collect();
+        let hot = hot
+            .into_iter()
+            .filter(|sensor| self.cooled_down(&sensor.name, now))
+            .collect();
+
+        let fans = failed_fans(&self.sysfs)
+            .into_iter()
+            .filter(|fan| self.cooled_down(fan, now))
+            .collect();
+
+        (hot, fans)
+    }
+
+    pub fn subscribe(mut self, event_sender: mpsc::Sender<Event>) {
+        if self.config.threshold_celsius.is_none() && self.config.crit_margin_celsius.is_none() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut interval =
+                tokio::time::interval(Duration::from_secs(self.config.interval_secs.max(1)));
+
+            loop {
+                interval.tick().await;
+
+                let (hot, fans) = self.check(Instant::now());
+                for sensor in hot {
+                    handle_overheating(&event_sender, sensor);
+                }
+                for fan in fans {
+                    handle_fan_failure(&event_sender, fan);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A throwaway sysfs tree under the system temp directory.
+    struct FakeSysfs(PathBuf);
+
+    impl FakeSysfs {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir()
+                .join(format!("sysnotifier-thermal-{name}-{}", std::process::id()));
+            _ = fs::remove_dir_all(&root);
+            Self(root)
+        }
+
+        fn write(&self, path: &str, value: &str) {
+            let path = self.0.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{value}\n")).unwrap();
+        }
+    }
+
+    impl Drop for FakeSysfs {
+        fn drop(&mut self) {
+            _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config() -> ThermalConfig {
+        ThermalConfig {
+            threshold_celsius: Some(90.0),
+            crit_margin_celsius: Some(5.0),
+            cooldown_secs: 300,
+            ..ThermalConfig::default()
+        }
+    }
+
+    fn laptop() -> FakeSysfs {
+        let sysfs = FakeSysfs::new("laptop");
+        sysfs.write("class/thermal/thermal_zone0/type", "x86_pkg_temp");
+        sysfs.write("class/thermal/thermal_zone0/temp", "45000");
+        sysfs.write("class/thermal/thermal_zone0/trip_point_0_type", "passive");
+        sysfs.write("class/thermal/thermal_zone0/trip_point_0_temp", "80000");
+        sysfs.write("class/thermal/thermal_zone0/trip_point_1_type", "critical");
+        sysfs.write("class/thermal/thermal_zone0/trip_point_1_temp", "100000");
+        sysfs.write("class/hwmon/hwmon1/name", "coretemp");
+        sysfs.write("class/hwmon/hwmon1/temp1_input", "97000");
+        sysfs.write("class/hwmon/hwmon1/temp1_label", "Package id 0");
+        sysfs.write("class/hwmon/hwmon1/temp1_crit", "100000");
+        sysfs.write("class/hwmon/hwmon1/temp2_input", "60000");
+        sysfs.write("class/hwmon/hwmon2/name", "thinkpad");
+        sysfs.write("class/hwmon/hwmon2/fan1_input", "0");
+        sysfs.write("class/hwmon/hwmon2/fan1_fault", "1");
+        sysfs.write("class/hwmon/hwmon2/fan2_input", "2400");
+        sysfs
+    }
+
+    #[test]
+    fn reads_thermal_zones_and_hwmon() {
+        let sysfs = laptop();
+
+        assert_eq!(
+            sensors(&sysfs.0),
+            vec![
+                Sensor {
+                    name: "x86_pkg_temp".into(),
+                    temperature: 45000,
+                    crit: Some(100000),
+                },
+                Sensor {
+                    name: "coretemp Package id 0".into(),
+                    temperature: 97000,
+                    crit: Some(100000),
+                },
+                Sensor {
+                    name: "coretemp temp2".into(),
+                    temperature: 60000,
+                    crit: None,
+                },
+            ]
+        );
+        assert_eq!(failed_fans(&sysfs.0), vec!["thinkpad fan1".to_string()]);
+    }
+
+    #[test]
+    fn warns_near_crit_or_above_threshold() {
+        let sensor = |temperature, crit| Sensor {
+            name: "cpu".into(),
+            temperature,
+            crit,
+        };
+
+        assert!(!overheating(&config(), &sensor(80000, None)));
+        assert!(overheating(&config(), &sensor(90000, None)));
+        assert!(overheating(&config(), &sensor(80000, Some(85000))));
+        assert!(!overheating(&config(), &sensor(79000, Some(85000))));
+
+        let crit_only = ThermalConfig {
+            threshold_celsius: None,
+            ..config()
+        };
+        assert!(!overheating(&crit_only, &sensor(95000, None)));
+    }
+
+    #[test]
+    fn cooldown_limits_repeated_warnings() {
+        let sysfs = laptop();
+        let mut monitor = ThermalMonitor::with_sysfs(config(), &sysfs.0);
+        let start = Instant::now();
+
+        let (hot, fans) = monitor.check(start);
+        assert_eq!(hot.len(), 1);
+        assert_eq!(hot[0].name, "coretemp Package id 0");
+        assert_eq!(fans, vec!["thinkpad fan1".to_string()]);
+
+        let (hot, fans) = monitor.check(start + Duration::from_secs(60));
+        assert!(hot.is_empty());
+        assert!(fans.is_empty());
+
+        let (hot, fans) = monitor.check(start + Duration::from_secs(300));
+        assert_eq!(hot.len(), 1);
+        assert_eq!(fans.len(), 1);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::Event;
+use futures_lite::StreamExt;
+use serde::Serialize;
+use std::{collections::HashMap, sync::mpsc};
+use zbus::{
+    fdo::ObjectManagerProxy,
+    proxy,
+    zvariant::{OwnedObjectPath, Value},
+};
+
+const FILESYSTEM_INTERFACE: &str = "org.freedesktop.UDisks2.Filesystem";
+const DRIVE_INTERFACE: &str = "org.freedesktop.UDisks2.Drive";
+
+/// A filesystem on a removable drive.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct StorageDevice {
+    /// UDisks2 object path of the block device.
+    pub path: String,
+    /// UDisks2 object path of the drive holding the filesystem.
+    pub drive: String,
+    pub label: String,
+    /// Device node, e.g. `/dev/sdb1`.
+    pub device: String,
+    pub size: u64,
+    pub mount_point: Option<String>,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageChange {
+    Inserted,
+    Mounted,
+    Unmounted,
+    Removed,
+    SafeToRemove,
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Block",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Block {
+    #[zbus(property)]
+    fn preferred_device(&self) -> zbus::Result<Vec<u8>>;
+
+    #[zbus(property)]
+    fn id_label(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn size(&self) -> zbus::Result<u64>;
+
+    #[zbus(property)]
+    fn drive(&self) -> zbus::Result<OwnedObjectPath>;
+
+    #[zbus(property)]
+    fn hint_ignore(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn hint_system(&self) -> zbus::Result<bool>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Filesystem",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Filesystem {
+    #[zbus(property)]
+    fn mount_points(&self) -> zbus::Result<Vec<Vec<u8>>>;
+
+    fn mount(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<String>;
+
+    fn unmount(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "org.freedesktop.UDisks2.Drive",
+    default_service = "org.freedesktop.UDisks2"
+)]
+trait Drive {
+    #[zbus(property)]
+    fn removable(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn vendor(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn model(&self) -> zbus::Result<String>;
+
+    fn power_off(&self, options: HashMap<&str, Value<'_>>) -> zbus::Result<()>;
+}
+
+/// Decodes a NUL terminated byte string as used for UDisks2 paths.
+fn bytestring(bytes: &[u8]) -> String {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+async fn block_proxy(
+    connection: &zbus::Connection,
+    path: &str,
+) -> zbus::Result<BlockProxy<'static>> {
+    BlockProxy::builder(connection)
+        .path(path.to_owned())?
+        .build()
+        .await
+}
+
+async fn filesystem_proxy(
+    connection: &zbus::Connection,
+    path: &str,
+) -> zbus::Result<FilesystemProxy<'static>> {
+    FilesystemProxy::builder(connection)
+        .path(path.to_owned())?
+        .build()
+        .await
+}
+
+async fn drive_proxy(
+    connection: &zbus::Connection,
+    path: &str,
+) -> zbus::Result<DriveProxy<'static>> {
+    DriveProxy::builder(connection)
+        .path(path.to_owned())?
+        .build()
+        .await
+}
+
+/// Describes the filesystem at `path`, `None` unless it lives on a removable drive.
+async fn describe(connection: &zbus::Connection, path: &str) -> Option<StorageDevice> {
+    let block = block_proxy(connection, path).await.ok()?;
+    if block.hint_ignore().await.unwrap_or(false) || block.hint_system().await.unwrap_or(true) {
+        return None;
+    }
+
+    let drive_path = block.drive().await.ok()?;
+    let drive = drive_proxy(connection, drive_path.as_str()).await.ok()?;
+    if !drive.removable().await.unwrap_or(false) {
+        return None;
+    }
+
+    let label = match block.id_label().await {
+        Ok(label) if !label.is_empty() => label,
+        _ => {
+            let vendor = drive.vendor().await.unwrap_or_default();
+            let model = drive.model().await.unwrap_or_default();
+            format!("{vendor} {model}").trim().to_string()
+        }
+    };
+
+    let filesystem = filesystem_proxy(connection, path).await.ok()?;
+    let mount_point = filesystem
+        .mount_points()
+        .await
+        .ok()
+        .and_then(|mount_points| mount_points.first().map(|m| bytestring(m)));
+
+    Some(StorageDevice {
+        path: path.into(),
+        drive: drive_path.to_string(),
+        label,
+        device: bytestring(&block.preferred_device().await.ok()?),
+        size: block.size().await.unwrap_or_default(),
+        mount_point,
+    })
+}
+
+fn handle_storage(
+    event_sender: &mpsc::Sender<Event>,
+    device: StorageDevice,
+    change: StorageChange,
+) {
+    _ = event_sender.send(Event::Storage(device, change));
+}
+
+/// Follows mounts and unmounts of one filesystem.
+fn watch(
+    connection: &zbus::Connection,
+    device: StorageDevice,
+    event_sender: &mpsc::Sender<Event>,
+) -> tokio::task::JoinHandle<()> {
+    let connection = connection.clone();
+    let event_sender = event_sender.clone();
+
+    tokio::spawn(async move {
+        let Ok(filesystem) = filesystem_proxy(&connection, &device.path).await else {
+            return;
+        };
+        let mut mount_points_stream = filesystem.receive_mount_points_changed().await;
+
+        let mut mount_point = device.mount_point.clone();
+        while let Some(event) = mount_points_stream.next().await {
+            let Ok(mount_points) = event.get().await else {
+                continue;
+            };
+
+            let current = mount_points.first().map(|m| bytestring(m));
+            if current == mount_point {
+                continue;
+            }
+
+            let change = if current.is_some() {
+                StorageChange::Mounted
+            } else {
+                StorageChange::Unmounted
+            };
+            mount_point = current.clone();
+            handle_storage(
+                &event_sender,
+                StorageDevice {
+                    mount_point: current,
+                    ..device.clone()
+                },
+                change,
+            );
+        }
+    })
+}
+
+pub struct StorageManager {
+    connection: zbus::Connection,
+}
+
+impl StorageManager {
+    pub async fn new() -> anyhow::Result<Self> {
+        let connection = zbus::Connection::system().await?;
+        let manager = Self { connection };
+
+        // Fail early when UDisks2 isn't running.
+        manager
+            .object_manager()
+            .await?
+            .get_managed_objects()
+            .await?;
+
+        Ok(manager)
+    }
+
+    async fn object_manager(&self) -> zbus::Result<ObjectManagerProxy<'static>> {
+        ObjectManagerProxy::builder(&self.connection)
+            .destination("org.freedesktop.UDisks2")?
+            .path("/org/freedesktop/UDisks2")?
+            .build()
+            .await
+    }
+
+    /// Mounts the filesystem if needed and returns its mount point.
+    pub async fn mount(&self, device: &StorageDevice) -> anyhow::Result<String> {
+        let filesystem = filesystem_proxy(&self.connection, &device.path).await?;
+        if let Some(mount_point) = filesystem.mount_points().await?.first() {
+            return Ok(bytestring(mount_point));
+        }
+
+        Ok(filesystem.mount(HashMap::new()).await?)
+    }
+
+    pub async fn unmount(&self, device: &StorageDevice) -> anyhow::Result<()> {
+        let filesystem = filesystem_proxy(&self.connection, &device.path).await?;
+        if !filesystem.mount_points().await?.is_empty() {
+            filesystem.unmount(HashMap::new()).await?;
+        }
+        Ok(())
+    }
+
+    /// Unmounts the filesystem and powers off its drive so it can be unplugged.
+    pub async fn power_off(&self, device: &StorageDevice) -> anyhow::Result<()> {
+        self.unmount(device).await?;
+
+        let drive = drive_proxy(&self.connection, &device.drive).await?;
+        drive.power_off(HashMap::new()).await?;
+        Ok(())
+    }
+
+    pub async fn subscribe(&self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let object_manager = self.object_manager().await?;
+        let mut added_stream = object_manager.receive_interfaces_added().await?;
+        let mut removed_stream = object_manager.receive_interfaces_removed().await?;
+        let objects = object_manager.get_managed_objects().await?;
+
+        let connection = self.connection.clone();
+        tokio::spawn(async move {
+            let mut watchers = HashMap::new();
+            for (path, interfaces) in objects {
+                if !interfaces.contains_key(FILESYSTEM_INTERFACE) {
+                    continue;
+                }
+                if let Some(device) = describe(&connection, path.as_str()).await {
+                    let watcher = watch(&connection, device.clone(), &event_sender);
+                    watchers.insert(path, (device, watcher));
+                }
+            }
+
+            loop {
+                tokio::select! {
+                    Some(signal) = added_stream.next() => {
+                        let Ok(args) = signal.args() else {
+                            continue;
+                        };
+                        if !args.interfaces_and_properties.contains_key(FILESYSTEM_INTERFACE) {
+                            continue;
+                        }
+                        let path = OwnedObjectPath::from(args.object_path.clone());
+                        if let Some(device) = describe(&connection, path.as_str()).await {
+                            handle_storage(&event_sender, device.clone(), StorageChange::Inserted);
+                            let watcher = watch(&connection, device.clone(), &event_sender);
+                            watchers.insert(path, (device, watcher));
+                        }
+                    }
+                    Some(signal) = removed_stream.next() => {
+                        let Ok(args) = signal.args() else {
+                            continue;
+                        };
+                        if args.interfaces.iter().any(|i| i.as_str() == DRIVE_INTERFACE) {
+                            _ = event_sender.send(Event::DriveRemoved(args.object_path.to_string()));
+                            continue;
+                        }
+                        if !args.interfaces.iter().any(|i| i.as_str() == FILESYSTEM_INTERFACE) {
+                            continue;
+                        }
+                        let path = OwnedObjectPath::from(args.object_path.clone());
+                        if let Some((device, watcher)) = watchers.remove(&path) {
+                            watcher.abort();
+                            handle_storage(
+                                &event_sender,
+                                StorageDevice { mount_point: None, ..device },
+                                StorageChange::Removed,
+                            );
+                        }
+                    }
+                    else => break,
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
@@ -1,11 +1,18 @@
 use crate::Event;
 use futures_lite::StreamExt;
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::{fmt::Display, sync::mpsc};
-use zbus::{proxy, zvariant::OwnedValue};
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex, mpsc},
+    time::Duration,
+};
+use zbus::{
+    proxy,
+    zvariant::{OwnedObjectPath, OwnedValue},
+};
 
 #[derive(
-    PartialEq, Eq, OwnedValue, Deserialize_repr, Serialize_repr, Default, Hash, Clone, Copy,
+    PartialEq, Eq, OwnedValue, Deserialize_repr, Serialize_repr, Default, Hash, Clone, Copy, Debug,
 )]
 #[repr(u32)]
 pub enum BatteryState {
@@ -35,7 +42,7 @@ impl Display for BatteryState {
 }
 
 #[derive(
-    PartialEq, Eq, OwnedValue, Deserialize_repr, Serialize_repr, Default, Hash, Clone, Copy,
+    PartialEq, Eq, OwnedValue, Deserialize_repr, Serialize_repr, Default, Hash, Clone, Copy, Debug,
 )]
 #[repr(u32)]
 pub enum BatteryLevel {
@@ -65,6 +72,18 @@ impl Display for BatteryLevel {
     }
 }
 
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+pub struct BatterySnapshot {
+    pub percentage: u64,
+    pub state: BatteryState,
+    pub level: BatteryLevel,
+    pub on_battery: bool,
+    /// Seconds until the battery is empty, 0 when unknown.
+    pub time_to_empty: i64,
+    /// Seconds until the battery is full, 0 when unknown.
+    pub time_to_full: i64,
+}
+
 #[proxy(interface = "org.freedesktop.UPower", assume_defaults = true)]
 trait UPower {
     #[zbus(property)]
@@ -72,6 +91,8 @@ trait UPower {
 
     #[zbus(object = "Device")]
     fn get_display_device(&self);
+
+    fn enumerate_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
 }
 
 #[proxy(
@@ -88,64 +109,324 @@ trait Device {
 
     #[zbus(property)]
     fn state(&self) -> zbus::Result<BatteryState>;
+
+    #[zbus(property)]
+    fn time_to_empty(&self) -> zbus::Result<i64>;
+
+    #[zbus(property)]
+    fn time_to_full(&self) -> zbus::Result<i64>;
+
+    #[zbus(property)]
+    fn charge_threshold_supported(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn charge_threshold_enabled(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn charge_start_threshold(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn charge_end_threshold(&self) -> zbus::Result<u32>;
+
+    fn enable_charge_threshold(&self, charge_threshold: bool) -> zbus::Result<()>;
+
+    #[zbus(property, name = "Type")]
+    fn type_(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn power_supply(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn capacity(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn energy_full(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn energy_full_design(&self) -> zbus::Result<f64>;
+
+    #[zbus(property)]
+    fn charge_cycles(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn technology(&self) -> zbus::Result<u32>;
+}
+
+const DEVICE_TYPE_BATTERY: u32 = 2;
+
+/// How often to check whether a battery health sample is due, samples are
+/// taken at most this late.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Wear information of the system battery.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct BatteryHealth {
+    /// Full energy as a percentage of the design energy.
+    pub capacity: f64,
+    pub energy_full: f64,
+    pub energy_full_design: f64,
+    /// Number of charge cycles, -1 when unknown.
+    pub charge_cycles: i32,
+    pub technology: &'static str,
+}
+
+fn technology_name(technology: u32) -> &'static str {
+    match technology {
+        1 => "lithium-ion",
+        2 => "lithium-polymer",
+        3 => "lithium-iron-phosphate",
+        4 => "lead-acid",
+        5 => "nickel-cadmium",
+        6 => "nickel-metal-hydride",
+        _ => "unknown",
+    }
 }
 
-fn handle_state(event_sender: &mpsc::Sender<Event>, value: BatteryState) {
+/// Hardware charge limit exposed by UPower 1.90 and newer.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ChargeThreshold {
+    pub enabled: bool,
+    pub start: u32,
+    pub end: u32,
+}
+
+fn handle_state(
+    snapshot: &Mutex<BatterySnapshot>,
+    event_sender: &mpsc::Sender<Event>,
+    value: BatteryState,
+) {
+    snapshot.lock().unwrap().state = value;
     _ = event_sender.send(Event::BatteryState(value));
 }
 
-fn handle_battery_level(event_sender: &mpsc::Sender<Event>, value: BatteryLevel) {
+fn handle_battery_level(
+    snapshot: &Mutex<BatterySnapshot>,
+    event_sender: &mpsc::Sender<Event>,
+    value: BatteryLevel,
+) {
+    snapshot.lock().unwrap().level = value;
     _ = event_sender.send(Event::BatteryLevel(value));
 }
 
-fn handle_on_battery(event_sender: &mpsc::Sender<Event>, value: bool) {
+fn handle_on_battery(
+    snapshot: &Mutex<BatterySnapshot>,
+    event_sender: &mpsc::Sender<Event>,
+    value: bool,
+) {
+    snapshot.lock().unwrap().on_battery = value;
     _ = event_sender.send(Event::OnBattery(value));
 }
 
-fn handle_battery_percentage(event_sender: &mpsc::Sender<Event>, value: f64) {
+fn handle_battery_percentage(
+    snapshot: &Mutex<BatterySnapshot>,
+    event_sender: &mpsc::Sender<Event>,
+    value: f64,
+) {
+    snapshot.lock().unwrap().percentage = value as u64;
     _ = event_sender.send(Event::BatteryPercentage(value as u64));
 }
 
+async fn read_snapshot(
+    upower: &UPowerProxy<'_>,
+    device: &DeviceProxy<'_>,
+) -> anyhow::Result<BatterySnapshot> {
+    Ok(BatterySnapshot {
+        percentage: device.percentage().await? as u64,
+        state: device.state().await?,
+        level: device.battery_level().await?,
+        on_battery: upower.on_battery().await.unwrap_or_default(),
+        time_to_empty: device.time_to_empty().await.unwrap_or_default(),
+        time_to_full: device.time_to_full().await.unwrap_or_default(),
+    })
+}
+
 pub struct BatteryManager {
     connection: zbus::Connection,
-    percentage: u64,
+    snapshot: Arc<Mutex<BatterySnapshot>>,
 }
 
 impl BatteryManager {
     pub async fn new() -> anyhow::Result<Self> {
         let connection = zbus::Connection::system().await?;
 
-        Ok(Self {
+        Ok(Self::with_connection(connection))
+    }
+
+    pub fn with_connection(connection: zbus::Connection) -> Self {
+        Self {
             connection,
-            percentage: 0,
+            snapshot: Arc::new(Mutex::new(BatterySnapshot::default())),
+        }
+    }
+
+    /// Latest known battery state, updated before the corresponding event is sent.
+    pub fn snapshot(&self) -> BatterySnapshot {
+        *self.snapshot.lock().unwrap()
+    }
+
+    /// Re-reads every property with fresh proxies and sends events for whatever
+    /// changed, for when property streams may have missed updates (e.g. during sleep).
+    pub async fn resync(&self, event_sender: &mpsc::Sender<Event>) -> anyhow::Result<()> {
+        let upower = UPowerProxy::new(&self.connection).await?;
+        let device = upower.get_display_device().await?;
+
+        let current = read_snapshot(&upower, &device).await?;
+        let previous = {
+            let mut snapshot = self.snapshot.lock().unwrap();
+            let previous = *snapshot;
+            snapshot.time_to_empty = current.time_to_empty;
+            snapshot.time_to_full = current.time_to_full;
+            previous
+        };
+
+        if current.percentage != previous.percentage {
+            handle_battery_percentage(&self.snapshot, event_sender, current.percentage as f64);
+        }
+        if current.on_battery != previous.on_battery {
+            handle_on_battery(&self.snapshot, event_sender, current.on_battery);
+        }
+        if current.state != previous.state {
+            handle_state(&self.snapshot, event_sender, current.state);
+        }
+        if current.level != previous.level {
+            handle_battery_level(&self.snapshot, event_sender, current.level);
+        }
+
+        Ok(())
+    }
+
+    async fn threshold_device(&self) -> Option<DeviceProxy<'static>> {
+        let upower = UPowerProxy::new(&self.connection).await.ok()?;
+
+        for path in upower.enumerate_devices().await.ok()? {
+            let Ok(builder) = DeviceProxy::builder(&self.connection).path(path) else {
+                continue;
+            };
+            let Ok(device) = builder.build().await else {
+                continue;
+            };
+            if device.charge_threshold_supported().await.unwrap_or(false) {
+                return Some(device);
+            }
+        }
+
+        None
+    }
+
+    async fn system_battery(&self) -> Option<DeviceProxy<'static>> {
+        let upower = UPowerProxy::new(&self.connection).await.ok()?;
+
+        for path in upower.enumerate_devices().await.ok()? {
+            let Ok(builder) = DeviceProxy::builder(&self.connection).path(path) else {
+                continue;
+            };
+            let Ok(device) = builder.build().await else {
+                continue;
+            };
+            if device.type_().await.ok() == Some(DEVICE_TYPE_BATTERY)
+                && device.power_supply().await.unwrap_or(false)
+            {
+                return Some(device);
+            }
+        }
+
+        None
+    }
+
+    /// Wear of the first system battery, `None` on machines without one.
+    pub async fn health(&self) -> Option<BatteryHealth> {
+        let device = self.system_battery().await?;
+
+        Some(BatteryHealth {
+            capacity: device.capacity().await.ok()?,
+            energy_full: device.energy_full().await.ok()?,
+            energy_full_design: device.energy_full_design().await.ok()?,
+            charge_cycles: device.charge_cycles().await.unwrap_or(-1),
+            technology: technology_name(device.technology().await.unwrap_or_default()),
         })
     }
 
-    pub fn set_battery(&mut self, percentage: u64) {
-        self.percentage = percentage;
+    /// Ticks [`Event::BatteryHealth`] every [`HEALTH_CHECK_INTERVAL`], whether
+    /// a sample is due is up to the receiver.
+    pub fn subscribe_health(&self, event_sender: mpsc::Sender<Event>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if event_sender.send(Event::BatteryHealth).is_err() {
+                    break;
+                }
+            }
+        });
     }
 
-    pub fn get_battery(&self) -> u64 {
-        self.percentage
+    /// Charge limit of the first battery supporting one, `None` on older UPower versions.
+    pub async fn charge_threshold(&self) -> Option<ChargeThreshold> {
+        let device = self.threshold_device().await?;
+
+        Some(ChargeThreshold {
+            enabled: device.charge_threshold_enabled().await.ok()?,
+            start: device.charge_start_threshold().await.ok()?,
+            end: device.charge_end_threshold().await.ok()?,
+        })
+    }
+
+    pub async fn set_charge_threshold(&self, enabled: bool) -> anyhow::Result<()> {
+        let device = self
+            .threshold_device()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("No battery supports charge thresholds"))?;
+
+        device.enable_charge_threshold(enabled).await?;
+        Ok(())
     }
 
     pub async fn subscribe(&mut self, event_sender: mpsc::Sender<Event>) -> anyhow::Result<()> {
         let upower = UPowerProxy::new(&self.connection).await?;
         let device = upower.get_display_device().await?;
 
-        self.percentage = device.percentage().await? as u64;
+        // Read every property up front so that the initial events below never
+        // observe a partially filled snapshot.
+        let initial = read_snapshot(&upower, &device).await?;
+        *self.snapshot.lock().unwrap() = initial;
 
         {
-            let percentage = device.percentage().await?;
-            handle_battery_percentage(&event_sender, percentage);
+            handle_battery_percentage(&self.snapshot, &event_sender, initial.percentage as f64);
 
             let mut percentage_stream = device.receive_percentage_changed().await;
 
+            let snapshot = Arc::clone(&self.snapshot);
             let event_sender = event_sender.clone();
             tokio::spawn(async move {
                 while let Some(event) = percentage_stream.next().await {
                     if let Ok(percentage) = event.get().await {
-                        handle_battery_percentage(&event_sender, percentage);
+                        handle_battery_percentage(&snapshot, &event_sender, percentage);
+                    }
+                }
+            });
+        }
+
+        {
+            let mut time_to_empty_stream = device.receive_time_to_empty_changed().await;
+
+            let snapshot = Arc::clone(&self.snapshot);
+            tokio::spawn(async move {
+                while let Some(event) = time_to_empty_stream.next().await {
+                    if let Ok(time_to_empty) = event.get().await {
+                        snapshot.lock().unwrap().time_to_empty = time_to_empty;
+                    }
+                }
+            });
+        }
+
+        {
+            let mut time_to_full_stream = device.receive_time_to_full_changed().await;
+
+            let snapshot = Arc::clone(&self.snapshot);
+            tokio::spawn(async move {
+                while let Some(event) = time_to_full_stream.next().await {
+                    if let Ok(time_to_full) = event.get().await {
+                        snapshot.lock().unwrap().time_to_full = time_to_full;
                     }
                 }
             });
@@ -153,46 +434,45 @@ impl BatteryManager {
 
         {
             let mut on_battery_stream = upower.receive_on_battery_changed().await;
-            let event_sender = event_sender.clone();
-            if let Ok(on_battery) = upower.on_battery().await {
-                handle_on_battery(&event_sender, on_battery);
-            }
+            handle_on_battery(&self.snapshot, &event_sender, initial.on_battery);
 
+            let snapshot = Arc::clone(&self.snapshot);
+            let event_sender = event_sender.clone();
             tokio::spawn(async move {
                 while let Some(event) = on_battery_stream.next().await {
                     if let Ok(on_battery) = event.get().await {
-                        handle_on_battery(&event_sender, on_battery);
+                        handle_on_battery(&snapshot, &event_sender, on_battery);
                     }
                 }
             });
         }
 
         {
-            let state = device.state().await?;
-            handle_state(&event_sender, state);
+            handle_state(&self.snapshot, &event_sender, initial.state);
 
             let mut state_stream = device.receive_state_changed().await;
 
+            let snapshot = Arc::clone(&self.snapshot);
             let event_sender = event_sender.clone();
             tokio::spawn(async move {
                 while let Some(event) = state_stream.next().await {
                     if let Ok(state) = event.get().await {
-                        handle_state(&event_sender, state);
+                        handle_state(&snapshot, &event_sender, state);
                     }
                 }
             });
         }
 
-        let level = device.battery_level().await?;
-        handle_battery_level(&event_sender, level);
+        handle_battery_level(&self.snapshot, &event_sender, initial.level);
 
         let mut level_stream = device.receive_battery_level_changed().await;
 
+        let snapshot = Arc::clone(&self.snapshot);
         let event_sender = event_sender.clone();
         tokio::spawn(async move {
             while let Some(event) = level_stream.next().await {
                 if let Ok(level) = event.get().await {
-                    handle_battery_level(&event_sender, level);
+                    handle_battery_level(&snapshot, &event_sender, level);
                 }
             }
         });
@@ -200,3 +480,145 @@ impl BatteryManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::UnixStream;
+    use zbus::interface;
+
+    const DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+
+    struct FakeUPower {
+        on_battery: bool,
+    }
+
+    #[interface(name = "org.freedesktop.UPower")]
+    impl FakeUPower {
+        #[zbus(property)]
+        fn on_battery(&self) -> bool {
+            self.on_battery
+        }
+
+        fn get_display_device(&self) -> OwnedObjectPath {
+            OwnedObjectPath::try_from(DISPLAY_DEVICE).unwrap()
+        }
+
+        fn enumerate_devices(&self) -> Vec<OwnedObjectPath> {
+            Vec::new()
+        }
+    }
+
+    struct FakeDevice {
+        percentage: f64,
+        state: BatteryState,
+        level: BatteryLevel,
+    }
+
+    #[interface(name = "org.freedesktop.UPower.Device")]
+    impl FakeDevice {
+        #[zbus(property)]
+        fn percentage(&self) -> f64 {
+            self.percentage
+        }
+
+        #[zbus(property)]
+        fn state(&self) -> u32 {
+            self.state as u32
+        }
+
+        #[zbus(property)]
+        fn battery_level(&self) -> u32 {
+            self.level as u32
+        }
+
+        #[zbus(property)]
+        fn time_to_empty(&self) -> i64 {
+            0
+        }
+
+        #[zbus(property)]
+        fn time_to_full(&self) -> i64 {
+            3600
+        }
+    }
+
+    async fn fake_upower() -> anyhow::Result<(zbus::Connection, zbus::Connection)> {
+        let (server, client) = UnixStream::pair()?;
+        let guid = zbus::Guid::generate();
+
+        let server = zbus::connection::Builder::unix_stream(server)
+            .server(guid)?
+            .p2p()
+            .serve_at("/org/freedesktop/UPower", FakeUPower { on_battery: false })?
+            .serve_at(
+                DISPLAY_DEVICE,
+                FakeDevice {
+                    percentage: 41.0,
+                    state: BatteryState::Discharging,
+                    level: BatteryLevel::Normal,
+                },
+            )?
+            .build();
+        let client = zbus::connection::Builder::unix_stream(client).p2p().build();
+
+        Ok(futures_lite::future::try_zip(server, client).await?)
+    }
+
+    fn recv(rx: &mpsc::Receiver<Event>) -> Event {
+        rx.recv_timeout(Duration::from_secs(5))
+            .expect("no event from fake UPower")
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn snapshot_follows_property_changes() -> anyhow::Result<()> {
+        let (server, client) = fake_upower().await?;
+        let (tx, rx) = mpsc::channel();
+
+        let mut battery = BatteryManager::with_connection(client);
+        battery.subscribe(tx).await?;
+
+        // Every initial event must already see the complete snapshot.
+        for _ in 0..4 {
+            recv(&rx);
+            assert_eq!(
+                battery.snapshot(),
+                BatterySnapshot {
+                    percentage: 41,
+                    state: BatteryState::Discharging,
+                    level: BatteryLevel::Normal,
+                    on_battery: false,
+                    time_to_empty: 0,
+                    time_to_full: 3600,
+                }
+            );
+        }
+
+        let device = server
+            .object_server()
+            .interface::<_, FakeDevice>(DISPLAY_DEVICE)
+            .await?;
+
+        device.get_mut().await.percentage = 57.0;
+        device
+            .get()
+            .await
+            .percentage_changed(device.signal_emitter())
+            .await?;
+        assert!(recv(&rx) == Event::BatteryPercentage(57));
+
+        device.get_mut().await.state = BatteryState::Charging;
+        device
+            .get()
+            .await
+            .state_changed(device.signal_emitter())
+            .await?;
+        assert!(recv(&rx) == Event::BatteryState(BatteryState::Charging));
+
+        let snapshot = battery.snapshot();
+        assert_eq!(snapshot.percentage, 57);
+        assert_eq!(snapshot.state, BatteryState::Charging);
+
+        Ok(())
+    }
+}